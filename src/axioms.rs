@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use super::checker::{CheckError, CheckResult};
+use super::deps::{dependency_chain, direct_dependencies};
+use super::dialect::Dialect;
+use super::environment::{Decl, Environment, NameId};
+
+/*
+ * The axioms each declaration transitively depends on, the equivalent of
+ * Lean's #print axioms. Results are memoized, so asking for every
+ * declaration of an environment visits each declaration once.
+ */
+
+pub struct AxiomDeps<'a> {
+    env: &'a Environment,
+    memo: HashMap<NameId, Vec<NameId>>,
+}
+
+impl<'a> AxiomDeps<'a> {
+    pub fn new(env: &'a Environment) -> Self {
+        Self {
+            env,
+            memo: HashMap::new(),
+        }
+    }
+
+    // Axioms `nidx` depends on, including itself if it is one, in export order
+    pub fn axioms(&mut self, nidx: NameId) -> &[NameId] {
+        // Post-order walk so that the dependencies are known first
+        let mut stack = vec![(nidx, None)];
+        let mut in_progress = HashSet::new();
+        while let Some((n, deps)) = stack.pop() {
+            if self.memo.contains_key(&n) {
+                continue;
+            }
+            let deps: Vec<NameId> = match deps {
+                Some(deps) => deps,
+                None => {
+                    let deps = direct_dependencies(self.env, n);
+                    in_progress.insert(n);
+                    // A dependency still in progress is part of a cycle,
+                    // which a well-formed export does not have
+                    let pending = deps
+                        .iter()
+                        .filter(|d| !in_progress.contains(*d))
+                        .map(|d| (*d, None))
+                        .collect::<Vec<_>>();
+                    stack.push((n, Some(deps)));
+                    stack.extend(pending);
+                    continue;
+                }
+            };
+            let mut axioms = deps
+                .iter()
+                .flat_map(|d| self.memo.get(d).into_iter().flatten())
+                .copied()
+                .collect::<Vec<_>>();
+            if let Some(Decl::Axiom(..)) = self.env.decl(n) {
+                axioms.push(n);
+            }
+            axioms.sort_by_key(|a| self.env.decl_position(*a));
+            axioms.dedup();
+            in_progress.remove(&n);
+            self.memo.insert(n, axioms);
+        }
+        &self.memo[&nidx]
+    }
+}
+
+/*
+ * The axioms declarations may depend on: either the given list, or any
+ * axiom except the one standing in for missing proofs, sorryAx in Lean 4
+ * and sorry_ax in Lean 3.
+ */
+pub enum AllowedAxioms {
+    AllButSorry,
+    Only(HashSet<String>),
+}
+
+impl AllowedAxioms {
+    pub fn allows(&self, dialect: Dialect, name: &str) -> bool {
+        match self {
+            AllowedAxioms::AllButSorry => name != dialect.sorry_axiom(),
+            AllowedAxioms::Only(names) => names.contains(name),
+        }
+    }
+}
+
+impl<'a> AxiomDeps<'a> {
+    // Fail if `nidx` uses a disallowed axiom; declaring one is fine
+    pub fn check_allowed(&mut self, nidx: NameId, allowed: &AllowedAxioms) -> CheckResult {
+        let env = self.env;
+        let disallowed = self
+            .axioms(nidx)
+            .iter()
+            .find(|a| **a != nidx && !allowed.allows(env.dialect(), &env.name_to_string(**a)));
+        match disallowed {
+            Some(a) => {
+                let chain = dependency_chain(env, nidx, *a).expect("Axiom not reachable");
+                let chain = chain.into_iter().map(|n| env.name_to_string(n)).collect();
+                Err(CheckError::DisallowedAxiom(chain))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_lines, ParseOptions};
+
+    #[test]
+    fn test_axioms() {
+        /*
+         * axiom a : Prop
+         * axiom b : Prop
+         * def c : Prop := a
+         * def d : Prop := c → b → c
+         */
+        let export = "\
+1 #NS 0 a
+2 #NS 0 b
+3 #NS 0 c
+4 #NS 0 d
+0 #ES 0
+1 #EC 1
+2 #EC 2
+3 #EC 3
+4 #EP #BD 0 2 3
+5 #EP #BD 0 3 4
+#AX 1 0
+#AX 2 0
+#DEF 3 0 1
+#DEF 4 0 5
+";
+        let env = parse_lines(export.as_bytes(), &ParseOptions::default(), &mut ()).unwrap();
+        let n = |name| env.find_name(name).unwrap();
+        let mut deps = AxiomDeps::new(&env);
+        assert_eq!(deps.axioms(n("a")), [n("a")]);
+        assert_eq!(deps.axioms(n("c")), [n("a")]);
+        assert_eq!(deps.axioms(n("d")), [n("a"), n("b")]);
+
+        let allowed = AllowedAxioms::Only(HashSet::from(["a".to_string()]));
+        assert_eq!(deps.check_allowed(n("b"), &allowed), Ok(()));
+        assert_eq!(deps.check_allowed(n("c"), &allowed), Ok(()));
+        assert_eq!(
+            deps.check_allowed(n("d"), &allowed),
+            Err(CheckError::DisallowedAxiom(vec![
+                "d".to_string(),
+                "b".to_string()
+            ]))
+        );
+        assert_eq!(
+            deps.check_allowed(n("d"), &AllowedAxioms::AllButSorry),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_sorry() {
+        // axiom <sorry> : Prop
+        // def a : Prop := <sorry>
+        for (dialect, sorry) in [(Dialect::Lean3, "sorry_ax"), (Dialect::Lean4, "sorryAx")] {
+            let export = format!(
+                "1 #NS 0 {}\n2 #NS 0 a\n0 #ES 0\n1 #EC 1\n#AX 1 0\n#DEF 2 0 1\n",
+                sorry
+            );
+            let options = ParseOptions { dialect };
+            let env = parse_lines(export.as_bytes(), &options, &mut ()).unwrap();
+            let a = env.find_name("a").unwrap();
+            assert_eq!(
+                AxiomDeps::new(&env).check_allowed(a, &AllowedAxioms::AllButSorry),
+                Err(CheckError::DisallowedAxiom(vec![
+                    "a".to_string(),
+                    sorry.to_string()
+                ]))
+            );
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+
+use super::dialect::Dialect;
+use super::environment::{Environment, ExprId, InfoAnnotation, LevelId, NameId, NameItem};
+
+/*
+ * Builds an environment in code, allocating the indices of names, levels and
+ * expressions in order as an export would. Meant for tests: adding an item
+ * the environment rejects, such as a second declaration with the same name,
+ * panics. The term! and level! macros below build terms with this.
+ *
+ *   let mut b = Builder::new();
+ *   let ty = term!(b, (pi {A : (Sort u)}, (A -> A)));
+ *   let val = term!(b, (fun {A : (Sort u)} => (fun a : A => a)));
+ *   b.def("id", ty, val, &["u"]);
+ */
+pub struct Builder {
+    env: Environment,
+    names: HashMap<String, NameId>,
+    next_name: u32,
+    next_level: u32,
+    next_expr: u32,
+    // binders enclosing the term being built by term!, innermost last
+    scope: Vec<String>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Self::with_dialect(Dialect::default())
+    }
+
+    pub fn with_dialect(dialect: Dialect) -> Self {
+        Self {
+            env: Environment::with_dialect(dialect),
+            names: HashMap::new(),
+            next_name: 1,
+            next_level: 1,
+            next_expr: 0,
+            scope: vec![],
+        }
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    pub fn build(self) -> Environment {
+        self.env
+    }
+
+    // A hierarchical name such as "Nat.succ"; "" is the anonymous name
+    pub fn name(&mut self, name: &str) -> NameId {
+        if name.is_empty() {
+            return NameId::from(0);
+        }
+        if let Some(nidx) = self.names.get(name) {
+            return *nidx;
+        }
+        let (parent, last) = match name.rsplit_once('.') {
+            Some((parent, last)) => (self.name(parent), last),
+            None => (NameId::from(0), name),
+        };
+        let item = match last.parse() {
+            Ok(i) => NameItem::Int(i),
+            Err(_) => NameItem::Str(last.to_string()),
+        };
+        let nidx = NameId::from(self.next_name);
+        self.next_name += 1;
+        self.env.add_name(nidx, item, parent).expect("Invalid name");
+        self.names.insert(name.to_string(), nidx);
+        nidx
+    }
+
+    fn names(&mut self, names: &[&str]) -> Vec<NameId> {
+        names.iter().map(|n| self.name(n)).collect()
+    }
+
+    fn new_level(&mut self) -> LevelId {
+        self.next_level += 1;
+        LevelId::from(self.next_level - 1)
+    }
+
+    fn new_expr(&mut self) -> ExprId {
+        self.next_expr += 1;
+        ExprId::from(self.next_expr - 1)
+    }
+
+    pub fn zero(&self) -> LevelId {
+        LevelId::from(0)
+    }
+
+    pub fn succ(&mut self, u: LevelId) -> LevelId {
+        let uidx = self.new_level();
+        self.env.add_level_succ(uidx, u).expect("Invalid level");
+        uidx
+    }
+
+    // The level n, as n successors of zero
+    pub fn level(&mut self, n: usize) -> LevelId {
+        (0..n).fold(self.zero(), |u, _| self.succ(u))
+    }
+
+    pub fn max(&mut self, u: LevelId, v: LevelId) -> LevelId {
+        let uidx = self.new_level();
+        self.env.add_level_max(uidx, u, v).expect("Invalid level");
+        uidx
+    }
+
+    pub fn imax(&mut self, u: LevelId, v: LevelId) -> LevelId {
+        let uidx = self.new_level();
+        self.env.add_level_imax(uidx, u, v).expect("Invalid level");
+        uidx
+    }
+
+    pub fn param(&mut self, name: &str) -> LevelId {
+        let nidx = self.name(name);
+        let uidx = self.new_level();
+        self.env.add_level_param(uidx, nidx).expect("Invalid level");
+        uidx
+    }
+
+    pub fn sort(&mut self, u: LevelId) -> ExprId {
+        let eidx = self.new_expr();
+        self.env.add_expr_sort(eidx, u).expect("Invalid expression");
+        eidx
+    }
+
+    pub fn prop(&mut self) -> ExprId {
+        let u = self.zero();
+        self.sort(u)
+    }
+
+    pub fn bvar(&mut self, i: usize) -> ExprId {
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_bound_var(eidx, i)
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn cnst(&mut self, name: &str, levels: &[LevelId]) -> ExprId {
+        let nidx = self.name(name);
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_constant(eidx, nidx, levels.to_vec())
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn app(&mut self, f: ExprId, a: ExprId) -> ExprId {
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_funappl(eidx, f, a)
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn apps(&mut self, f: ExprId, args: &[ExprId]) -> ExprId {
+        args.iter().fold(f, |f, a| self.app(f, *a))
+    }
+
+    pub fn pi(&mut self, name: &str, domain: ExprId, body: ExprId) -> ExprId {
+        self.pi_info(InfoAnnotation::Default, name, domain, body)
+    }
+
+    pub fn pi_info(
+        &mut self,
+        info: InfoAnnotation,
+        name: &str,
+        domain: ExprId,
+        body: ExprId,
+    ) -> ExprId {
+        let nidx = self.name(name);
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_pi(eidx, info, nidx, domain, body)
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn lam(&mut self, name: &str, domain: ExprId, body: ExprId) -> ExprId {
+        self.lam_info(InfoAnnotation::Default, name, domain, body)
+    }
+
+    pub fn lam_info(
+        &mut self,
+        info: InfoAnnotation,
+        name: &str,
+        domain: ExprId,
+        body: ExprId,
+    ) -> ExprId {
+        let nidx = self.name(name);
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_lambda(eidx, info, nidx, domain, body)
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn let_(&mut self, name: &str, ty: ExprId, val: ExprId, body: ExprId) -> ExprId {
+        let nidx = self.name(name);
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_let(eidx, nidx, ty, val, body)
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn proj(&mut self, structure: &str, field: usize, e: ExprId) -> ExprId {
+        let nidx = self.name(structure);
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_proj(eidx, nidx, field, e)
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn nat_lit(&mut self, digits: &str) -> ExprId {
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_nat_lit(eidx, digits.to_string())
+            .expect("Invalid expression");
+        eidx
+    }
+
+    pub fn str_lit(&mut self, s: &str) -> ExprId {
+        let eidx = self.new_expr();
+        self.env
+            .add_expr_str_lit(eidx, s.to_string())
+            .expect("Invalid expression");
+        eidx
+    }
+
+    // The variable bound by the innermost enclosing binder named `name` in
+    // term!, or else the constant `name` without universe levels
+    #[doc(hidden)]
+    pub fn var(&mut self, name: &str) -> ExprId {
+        match self.scope.iter().rev().position(|n| n == name) {
+            Some(i) => self.bvar(i),
+            None => self.cnst(name, &[]),
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn enter(&mut self, name: &str) {
+        self.scope.push(name.to_string());
+    }
+
+    #[doc(hidden)]
+    pub fn exit(&mut self) {
+        self.scope.pop();
+    }
+
+    pub fn axiom(&mut self, name: &str, ty: ExprId, params: &[&str]) -> NameId {
+        let nidx = self.name(name);
+        let params = self.names(params);
+        self.env
+            .add_axiom(nidx, ty, params)
+            .expect("Invalid declaration");
+        nidx
+    }
+
+    pub fn def(&mut self, name: &str, ty: ExprId, val: ExprId, params: &[&str]) -> NameId {
+        let nidx = self.name(name);
+        let params = self.names(params);
+        self.env
+            .add_definition(nidx, ty, val, params)
+            .expect("Invalid declaration");
+        nidx
+    }
+
+    // An inductive type with `num_params` parameters and the given
+    // constructors
+    pub fn inductive(
+        &mut self,
+        name: &str,
+        num_params: usize,
+        ty: ExprId,
+        intros: &[(&str, ExprId)],
+        params: &[&str],
+    ) -> NameId {
+        let nidx = self.name(name);
+        let intros = intros.iter().map(|(n, e)| (self.name(n), *e)).collect();
+        let params = self.names(params);
+        self.env
+            .add_inductive(num_params, nidx, ty, intros, params)
+            .expect("Invalid declaration");
+        nidx
+    }
+
+    pub fn quot(&mut self) {
+        self.env.add_quot().expect("Invalid declaration");
+    }
+}
+
+/*
+ * A term built with a Builder from Lean-like syntax, where each subterm is
+ * a single token or parenthesized group:
+ *
+ *   Prop, Type, (Sort u)          sorts, with a level as in level!
+ *   x                             bound variable x, or else the constant x
+ *   "Nat.succ"                    constant
+ *   (const "id" u ...)            constant with universe levels
+ *   (f a b)                       application
+ *   (fun x : A => b)              lambda, (fun {x : A} => b) if implicit
+ *   (pi x : A, B)                 Pi type, (pi {x : A}, B) if implicit
+ *   (A -> B)                      non-dependent Pi type
+ *   (let x : A := v; b)           let
+ *   {e}                           the Rust expression e, of type ExprId
+ */
+#[macro_export]
+macro_rules! term {
+    ($b:ident, Prop) => {
+        $b.prop()
+    };
+    ($b:ident, Type) => {{
+        let u = $b.level(1);
+        $b.sort(u)
+    }};
+    ($b:ident, (Sort $u:tt)) => {{
+        let u = $crate::level!($b, $u);
+        $b.sort(u)
+    }};
+    ($b:ident, (const $c:literal $($u:tt)*)) => {{
+        let levels = [$($crate::level!($b, $u)),*];
+        $b.cnst($c, &levels)
+    }};
+    ($b:ident, (fun {$x:ident : $t:tt} => $body:tt)) => {
+        $crate::term!(@binder $b, lam_info, Implicit, $x, $t, $body)
+    };
+    ($b:ident, (fun $x:ident : $t:tt => $body:tt)) => {
+        $crate::term!(@binder $b, lam_info, Default, $x, $t, $body)
+    };
+    ($b:ident, (pi {$x:ident : $t:tt}, $body:tt)) => {
+        $crate::term!(@binder $b, pi_info, Implicit, $x, $t, $body)
+    };
+    ($b:ident, (pi $x:ident : $t:tt, $body:tt)) => {
+        $crate::term!(@binder $b, pi_info, Default, $x, $t, $body)
+    };
+    ($b:ident, ($t:tt -> $body:tt)) => {{
+        let t = $crate::term!($b, $t);
+        $b.enter("");
+        let body = $crate::term!($b, $body);
+        $b.exit();
+        $b.pi("", t, body)
+    }};
+    ($b:ident, (let $x:ident : $t:tt := $v:tt; $body:tt)) => {{
+        let t = $crate::term!($b, $t);
+        let v = $crate::term!($b, $v);
+        $b.enter(stringify!($x));
+        let body = $crate::term!($b, $body);
+        $b.exit();
+        $b.let_(stringify!($x), t, v, body)
+    }};
+    ($b:ident, ($f:tt $($a:tt)+)) => {{
+        let f = $crate::term!($b, $f);
+        let args = [$($crate::term!($b, $a)),+];
+        $b.apps(f, &args)
+    }};
+    ($b:ident, ($e:tt)) => {
+        $crate::term!($b, $e)
+    };
+    ($b:ident, {$e:expr}) => {
+        $e
+    };
+    ($b:ident, $x:ident) => {
+        $b.var(stringify!($x))
+    };
+    ($b:ident, $c:literal) => {
+        $b.cnst($c, &[])
+    };
+    (@binder $b:ident, $make:ident, $info:ident, $x:ident, $t:tt, $body:tt) => {{
+        let t = $crate::term!($b, $t);
+        $b.enter(stringify!($x));
+        let body = $crate::term!($b, $body);
+        $b.exit();
+        $b.$make($crate::InfoAnnotation::$info, stringify!($x), t, body)
+    }};
+}
+
+/*
+ * A universe level built with a Builder: a number, a parameter name,
+ * (succ u), (max u v), (imax u v), or {e} for a Rust expression of type
+ * LevelId.
+ */
+#[macro_export]
+macro_rules! level {
+    ($b:ident, $n:literal) => {
+        $b.level($n)
+    };
+    ($b:ident, (succ $u:tt)) => {{
+        let u = $crate::level!($b, $u);
+        $b.succ(u)
+    }};
+    ($b:ident, (max $u:tt $v:tt)) => {{
+        let u = $crate::level!($b, $u);
+        let v = $crate::level!($b, $v);
+        $b.max(u, v)
+    }};
+    ($b:ident, (imax $u:tt $v:tt)) => {{
+        let u = $crate::level!($b, $u);
+        let v = $crate::level!($b, $v);
+        $b.imax(u, v)
+    }};
+    ($b:ident, {$e:expr}) => {
+        $e
+    };
+    ($b:ident, $p:ident) => {
+        $b.param(stringify!($p))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::check_declarations;
+
+    #[test]
+    fn test_builder() {
+        let mut b = Builder::new();
+        let ty = term!(b, (pi {A : (Sort u)}, (pi a : A, A)));
+        let val = term!(b, (fun {A : (Sort u)} => (fun a : A => a)));
+        let id = b.def("id", ty, val, &["u"]);
+        assert_eq!(
+            b.env().decl_to_string(id),
+            "definition id.{u} {A : Sort u}, (a : A), A := {A : Sort u}, (a : A), a"
+        );
+
+        let nat = b.cnst("Nat", &[]);
+        let (ty, succ_ty) = (term!(b, Type), term!(b, ("Nat" -> "Nat")));
+        b.inductive(
+            "Nat",
+            0,
+            ty,
+            &[("Nat.zero", nat), ("Nat.succ", succ_ty)],
+            &[],
+        );
+        let two = term!(b, ("Nat.succ" ("Nat.succ" "Nat.zero")));
+        let val = term!(b, (let x : "Nat" := {two}; ((const "id" 1) "Nat" x)));
+        b.def("two", nat, val, &[]);
+        let env = b.build();
+        assert_eq!(
+            env.expr_to_string(val),
+            "let x : Nat := (Nat.succ (Nat.succ Nat.zero)); ((id.{(succ 0)} Nat) x)"
+        );
+        let results = check_declarations(&env, env.declarations(), &mut ());
+        assert!(results.iter().all(|r| r.result.is_ok()));
+    }
+}
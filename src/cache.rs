@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use super::checker::check_references;
+use super::environment::{Environment, NameId};
+use super::hash::{DeclHash, DeclHashes};
+
+/*
+ * Hashes of the declarations known to check, kept on disk between runs. As
+ * the hash of a declaration covers everything it depends on, a declaration
+ * whose hash is in the cache checks without looking at it again, apart from
+ * where its dependencies are in the export. Entries only hold for the
+ * checker version, dialect and #QUOT setting of the header; a cache written
+ * under others is ignored.
+ *
+ *   lean-checker-cache <version> <dialect> <quot|noquot>
+ *   <hash>
+ *   ...
+ */
+pub struct VerificationCache {
+    header: String,
+    verified: HashSet<DeclHash>,
+}
+
+impl VerificationCache {
+    // An empty cache for checking `env`
+    pub fn new(env: &Environment) -> Self {
+        let quot = if env.has_quot() { "quot" } else { "noquot" };
+        Self {
+            header: format!(
+                "lean-checker-cache {} {} {}",
+                env!("CARGO_PKG_VERSION"),
+                env.dialect(),
+                quot
+            ),
+            verified: HashSet::new(),
+        }
+    }
+
+    // Lines with another header, or that are not hashes, are ignored
+    pub fn read<R: Read>(file: R, env: &Environment) -> io::Result<Self> {
+        let mut cache = Self::new(env);
+        let mut lines = BufReader::new(file).lines();
+        if let Some(header) = lines.next() {
+            if header? != cache.header {
+                return Ok(cache);
+            }
+        }
+        for line in lines {
+            if let Ok(h) = line?.parse() {
+                cache.verified.insert(h);
+            }
+        }
+        Ok(cache)
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{}", self.header)?;
+        for h in &self.verified {
+            writeln!(out, "{}", h)?;
+        }
+        Ok(())
+    }
+
+    // A missing file is an empty cache
+    pub fn load(path: &Path, env: &Environment) -> io::Result<Self> {
+        match File::open(path) {
+            Ok(file) => Self::read(file, env),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::new(env)),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Written to a temporary file first, so that an interrupted run leaves
+    // the previous cache intact
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        self.write(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn contains(&self, h: DeclHash) -> bool {
+        self.verified.contains(&h)
+    }
+
+    // Whether a declaration in the cache still refers to earlier ones only
+    pub fn is_verified(&self, env: &Environment, hashes: &mut DeclHashes, nidx: NameId) -> bool {
+        self.contains(hashes.hash(nidx)) && check_references(env, nidx).is_ok()
+    }
+
+    pub fn insert(&mut self, h: DeclHash) {
+        self.verified.insert(h);
+    }
+
+    pub fn len(&self) -> usize {
+        self.verified.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.verified.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_lines, Dialect, ParseOptions};
+
+    fn parse(export: &str) -> Environment {
+        parse_lines(export.as_bytes(), &ParseOptions::default(), &mut ()).unwrap()
+    }
+
+    #[test]
+    fn test_cache() {
+        let file = File::open("examples/id.export").unwrap();
+        let env = parse_lines(file, &ParseOptions::default(), &mut ()).unwrap();
+        let id = env.find_name("id").unwrap();
+        let h = DeclHashes::new(&env).hash(id);
+
+        let mut cache = VerificationCache::new(&env);
+        cache.insert(h);
+        let mut out = Vec::new();
+        cache.write(&mut out).unwrap();
+        let read = VerificationCache::read(out.as_slice(), &env).unwrap();
+        assert!(read.contains(h) && read.len() == 1);
+
+        // Entries of another configuration do not count
+        let other = Environment::with_dialect(Dialect::Lean3);
+        let read = VerificationCache::read(out.as_slice(), &other).unwrap();
+        assert!(read.is_empty());
+
+        let path = std::env::temp_dir().join(format!("lean-checker-{}.cache", std::process::id()));
+        assert!(VerificationCache::load(&path, &env).unwrap().is_empty());
+        cache.save(&path).unwrap();
+        assert!(VerificationCache::load(&path, &env).unwrap().contains(h));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reordered() {
+        /*
+         * axiom a : Prop
+         * def b : Prop := a
+         * and the same with b first
+         */
+        let names = "\
+1 #NS 0 a
+2 #NS 0 b
+0 #ES 0
+1 #EC 1
+";
+        let env = parse(&format!("{}#AX 1 0\n#DEF 2 0 1\n", names));
+        let reordered = parse(&format!("{}#DEF 2 0 1\n#AX 1 0\n", names));
+        let mut cache = VerificationCache::new(&env);
+        let mut hashes = DeclHashes::new(&env);
+        for nidx in env.declarations() {
+            cache.insert(hashes.hash(*nidx));
+        }
+        let b = env.find_name("b").unwrap();
+        assert!(cache.is_verified(&env, &mut hashes, b));
+        // The hashes are the same, but b now refers to a later declaration
+        let mut hashes = DeclHashes::new(&reordered);
+        assert!(cache.contains(hashes.hash(b)));
+        assert!(!cache.is_verified(&reordered, &mut hashes, b));
+    }
+}
@@ -0,0 +1,415 @@
+use core::fmt;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use super::environment::{Constant, Decl, Environment, Expr, ExprId, Level, LevelId, NameId};
+use super::observer::Observer;
+
+/*
+ * Structural checks of declarations: every constant refers to an earlier
+ * declaration and is given the right number of universe levels, universe
+ * parameters are declared, terms are closed, constructors target their
+ * inductive type and projections refer to existing fields. Terms are not
+ * type checked.
+ */
+
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum CheckError {
+    UnknownConstant(String),
+    DeclaredLater(String),
+    LevelCount(String, usize, usize),
+    UndeclaredLevelParam(String),
+    DuplicateLevelParam(String),
+    LooseBoundVar,
+    TooFewBinders(usize),
+    ConstructorName(String),
+    ConstructorType(String),
+    BadProjection(String, usize),
+    // The chain of constants leading to the axiom, which comes last
+    DisallowedAxiom(Vec<String>),
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::UnknownConstant(n) => write!(f, "Unknown constant {}", n),
+            CheckError::DeclaredLater(n) => write!(f, "Constant {} is declared later", n),
+            CheckError::LevelCount(n, expected, got) => write!(
+                f,
+                "Constant {} expects {} universe levels, got {}",
+                n, expected, got
+            ),
+            CheckError::UndeclaredLevelParam(n) => {
+                write!(f, "Undeclared universe parameter {}", n)
+            }
+            CheckError::DuplicateLevelParam(n) => {
+                write!(f, "Duplicate universe parameter {}", n)
+            }
+            CheckError::LooseBoundVar => write!(f, "Term has loose bound variables"),
+            CheckError::TooFewBinders(params) => {
+                write!(f, "Type has fewer than {} parameters", params)
+            }
+            CheckError::ConstructorName(n) => {
+                write!(f, "Constructor {} is not in the namespace of its type", n)
+            }
+            CheckError::ConstructorType(n) => {
+                write!(f, "Constructor {} does not construct its type", n)
+            }
+            CheckError::BadProjection(n, i) => write!(f, "{} has no field {}", n, i),
+            CheckError::DisallowedAxiom(chain) => write!(
+                f,
+                "Depends on disallowed axiom {} via {}",
+                chain.last().map_or("", |a| a.as_str()),
+                chain.join(" -> ")
+            ),
+        }
+    }
+}
+
+pub type CheckResult = Result<(), CheckError>;
+
+// Number of universe levels a constant takes
+fn level_counts(env: &Environment, constant: Constant) -> Vec<usize> {
+    let params = |ind: NameId| env.decl(ind).map_or(0, |d| d.level_params().len());
+    match constant {
+        Constant::Decl(n) | Constant::Constructor(n, _) => vec![params(n)],
+        // The recursor has an extra level unless it only eliminates into Prop
+        Constant::Recursor(n) => vec![params(n), params(n) + 1],
+        Constant::Quot(i) => vec![env.dialect().quot_constants()[i].1],
+    }
+}
+
+// Number of leading Pi binders
+fn pi_arity(env: &Environment, eidx: ExprId) -> usize {
+    let mut arity = 0;
+    let mut e = eidx;
+    while let Expr::Pi(_, _, _, body) = env.expr(e) {
+        arity += 1;
+        e = *body;
+    }
+    arity
+}
+
+// Strip the leading Pi binders and return the head of the result
+fn result_head(env: &Environment, eidx: ExprId) -> &Expr {
+    let mut e = eidx;
+    while let Expr::Pi(_, _, _, body) = env.expr(e) {
+        e = *body;
+    }
+    while let Expr::FunAppl(f, _) = env.expr(e) {
+        e = *f;
+    }
+    env.expr(e)
+}
+
+struct DeclChecker<'a> {
+    env: &'a Environment,
+    nidx: NameId,
+    position: usize,
+    level_params: &'a [NameId],
+    visited_exprs: HashSet<ExprId>,
+    visited_levels: HashSet<LevelId>,
+}
+
+impl<'a> DeclChecker<'a> {
+    fn check_level_params(&self) -> CheckResult {
+        let mut seen = HashSet::new();
+        for n in self.level_params {
+            if !seen.insert(*n) {
+                return Err(CheckError::DuplicateLevelParam(self.env.name_to_string(*n)));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_level(&mut self, uidx: LevelId) -> CheckResult {
+        let mut stack = vec![uidx];
+        while let Some(uidx) = stack.pop() {
+            if !self.visited_levels.insert(uidx) {
+                continue;
+            }
+            match self.env.level(uidx) {
+                Level::Zero => {}
+                Level::Succ(u) => stack.push(*u),
+                Level::Max(u1, u2) | Level::IMax(u1, u2) => stack.extend([*u1, *u2]),
+                Level::Param(n) => {
+                    if !self.level_params.contains(n) {
+                        let name = self.env.name_to_string(*n);
+                        return Err(CheckError::UndeclaredLevelParam(name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_constant(&self, n: NameId, levels: &[LevelId]) -> CheckResult {
+        let name = || self.env.name_to_string(n);
+        let constant = self
+            .env
+            .resolve_constant(n)
+            .ok_or_else(|| CheckError::UnknownConstant(name()))?;
+        if let Some(d) = self.env.constant_decl(n) {
+            // An inductive type may refer to itself in its constructors
+            let allowed = d == self.nidx
+                && constant == Constant::Decl(d)
+                && matches!(self.env.decl(d), Some(Decl::Ind(..)));
+            let position = self.env.decl_position(d).expect("Declaration not found");
+            if position > self.position || (position == self.position && !allowed) {
+                return Err(CheckError::DeclaredLater(name()));
+            }
+        }
+        let counts = level_counts(self.env, constant);
+        if !counts.contains(&levels.len()) {
+            return Err(CheckError::LevelCount(name(), counts[0], levels.len()));
+        }
+        Ok(())
+    }
+
+    fn check_projection(&self, n: NameId, field: usize) -> CheckResult {
+        let name = self.env.name_to_string(n);
+        if let Some(Decl::Ind(params, _, intros, _)) = self.env.decl(n) {
+            if let [(_, ctor_type)] = intros.as_slice() {
+                let arity = pi_arity(self.env, *ctor_type);
+                if field.checked_add(*params).is_some_and(|i| i < arity) {
+                    return Ok(());
+                }
+            }
+        }
+        Err(CheckError::BadProjection(name, field))
+    }
+
+    fn check_expr(&mut self, root: ExprId) -> CheckResult {
+        if self.env.loose_bvar_range(root) > 0 {
+            return Err(CheckError::LooseBoundVar);
+        }
+        let mut stack = vec![root];
+        while let Some(eidx) = stack.pop() {
+            if !self.visited_exprs.insert(eidx) {
+                continue;
+            }
+            let expr = self.env.expr(eidx);
+            match expr {
+                Expr::Sort(u) => self.check_level(*u)?,
+                Expr::Constant(n, levels) => {
+                    self.check_constant(*n, levels)?;
+                    levels.iter().try_for_each(|u| self.check_level(*u))?;
+                }
+                Expr::Proj(n, field, _) => self.check_projection(*n, *field)?,
+                _ => {}
+            }
+            stack.extend(expr.children());
+        }
+        Ok(())
+    }
+
+    fn check_inductive(
+        &mut self,
+        params: usize,
+        eidx: ExprId,
+        intros: &[(NameId, ExprId)],
+    ) -> CheckResult {
+        if pi_arity(self.env, eidx) < params {
+            return Err(CheckError::TooFewBinders(params));
+        }
+        let prefix = format!("{}.", self.env.name_to_string(self.nidx));
+        for (ni, ei) in intros {
+            let name = || self.env.name_to_string(*ni);
+            if !name().starts_with(&prefix) {
+                return Err(CheckError::ConstructorName(name()));
+            }
+            if pi_arity(self.env, *ei) < params {
+                return Err(CheckError::TooFewBinders(params));
+            }
+            match result_head(self.env, *ei) {
+                Expr::Constant(n, _) if *n == self.nidx => {}
+                _ => return Err(CheckError::ConstructorType(name())),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn check_declaration(env: &Environment, nidx: NameId) -> CheckResult {
+    let decl = env.decl(nidx).expect("Declaration not found");
+    let mut checker = DeclChecker {
+        env,
+        nidx,
+        position: env.decl_position(nidx).expect("Declaration not found"),
+        level_params: decl.level_params(),
+        visited_exprs: HashSet::new(),
+        visited_levels: HashSet::new(),
+    };
+    checker.check_level_params()?;
+    for eidx in decl.exprs() {
+        checker.check_expr(eidx)?;
+    }
+    if let Decl::Ind(params, eidx, intros, _) = decl {
+        checker.check_inductive(*params, *eidx, intros)?;
+    }
+    Ok(())
+}
+
+/*
+ * The checks of a declaration that depend on the rest of the environment
+ * rather than on its own contents: that the constants it refers to resolve
+ * and are declared earlier.
+ */
+pub(crate) fn check_references(env: &Environment, nidx: NameId) -> CheckResult {
+    let decl = env.decl(nidx).expect("Declaration not found");
+    let checker = DeclChecker {
+        env,
+        nidx,
+        position: env.decl_position(nidx).expect("Declaration not found"),
+        level_params: decl.level_params(),
+        visited_exprs: HashSet::new(),
+        visited_levels: HashSet::new(),
+    };
+    let mut visited = HashSet::new();
+    let mut stack = decl.exprs();
+    while let Some(eidx) = stack.pop() {
+        if !visited.insert(eidx) {
+            continue;
+        }
+        let expr = env.expr(eidx);
+        if let Expr::Constant(n, levels) = expr {
+            checker.check_constant(*n, levels)?;
+        }
+        stack.extend(expr.children());
+    }
+    Ok(())
+}
+
+pub struct DeclResult {
+    pub nidx: NameId,
+    pub result: CheckResult,
+    pub duration: Duration,
+    // taken as checked from a verification cache
+    pub cached: bool,
+}
+
+pub fn check_declaration_timed(env: &Environment, nidx: NameId) -> DeclResult {
+    let start = Instant::now();
+    let result = check_declaration(env, nidx);
+    DeclResult {
+        nidx,
+        result,
+        duration: start.elapsed(),
+        cached: false,
+    }
+}
+
+// Check the given declarations in order, passing each result to the observer
+pub fn check_declarations(
+    env: &Environment,
+    nidxs: &[NameId],
+    observer: &mut dyn Observer,
+) -> Vec<DeclResult> {
+    nidxs
+        .iter()
+        .map(|nidx| {
+            let result = check_declaration_timed(env, *nidx);
+            observer.on_decl_checked(env, &result);
+            result
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_lines, ParseOptions};
+
+    const ID: &str = "\
+1 #NS 0 id
+2 #NS 0 α
+3 #NS 0 u
+1 #UP 3
+0 #ES 1
+4 #NS 0 a
+1 #EV 0
+2 #EV 1
+3 #EP #BD 4 1 2
+4 #EP #BI 2 0 3
+5 #EL #BD 4 1 1
+6 #EL #BI 2 0 5
+";
+
+    fn check_str(s: &str) -> Vec<CheckResult> {
+        let env = parse_lines(s.as_bytes(), &ParseOptions::default(), &mut ()).unwrap();
+        check_declarations(&env, env.declarations(), &mut ())
+            .into_iter()
+            .map(|r| r.result)
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let decls = "\
+5 #NS 0 p
+2 #US 0
+3 #US 2
+7 #ES 2
+8 #ES 0
+9 #EC 1 3
+10 #EA 9 7
+11 #EA 10 8
+#DEF 1 4 6 3
+#DEF 5 7 11
+";
+        assert_eq!(check_str(&format!("{}{}", ID, decls)), vec![Ok(()), Ok(())]);
+    }
+
+    #[test]
+    fn test_check_errors() {
+        // `id` with an undeclared universe parameter
+        assert_eq!(
+            check_str(&format!("{}#DEF 1 4 6\n", ID)),
+            vec![Err(CheckError::UndeclaredLevelParam("u".to_string()))]
+        );
+        // A loose bound variable
+        assert_eq!(
+            check_str(&format!("{}#AX 4 1 3\n", ID)),
+            vec![Err(CheckError::LooseBoundVar)]
+        );
+        // A constant used before it is declared
+        let decls = "\
+5 #NS 0 p
+7 #EC 1 1
+#DEF 5 4 7 3
+#DEF 1 4 6 3
+";
+        assert_eq!(
+            check_str(&format!("{}{}", ID, decls)),
+            vec![Err(CheckError::DeclaredLater("id".to_string())), Ok(())]
+        );
+        // A constant with the wrong number of universe levels
+        let decls = "\
+5 #NS 0 p
+7 #EC 1
+#DEF 1 4 6 3
+#DEF 5 4 7 3
+";
+        assert_eq!(
+            check_str(&format!("{}{}", ID, decls)),
+            vec![Ok(()), Err(CheckError::LevelCount("id".to_string(), 1, 0))]
+        );
+        // A field index too large to add to the parameters
+        let decls = "\
+1 #NS 0 U
+2 #NS 1 mk
+3 #NS 0 f
+1 #US 0
+0 #ES 1
+1 #EC 1
+2 #EJ 1 18446744073709551615 1
+#IND 1 1 0 1 2 1
+#DEF 3 1 2
+";
+        assert_eq!(
+            check_str(decls)[1],
+            Err(CheckError::BadProjection("U".to_string(), usize::MAX))
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+
+use super::deps::{direct_dependencies, expr_dependencies};
+use super::environment::{
+    Constant, Decl, EnvError, EnvResult, Environment, Expr, ExprId, Level, LevelId, NameId,
+    NameItem,
+};
+
+/*
+ * An environment items are copied into. Names equal to ones already
+ * copied are shared, and levels and expressions are interned by the
+ * environment, so equal items get equal indices whichever environment they
+ * come from. New items are numbered densely in the order they are copied.
+ */
+pub(crate) struct Destination {
+    pub(crate) env: Environment,
+    names: HashMap<(NameItem, NameId), NameId>,
+}
+
+impl Destination {
+    pub(crate) fn new(env: Environment) -> Self {
+        Self {
+            env,
+            names: HashMap::new(),
+        }
+    }
+
+    fn name(&mut self, item: &NameItem, parent: NameId) -> NameId {
+        let key = (item.clone(), parent);
+        if let Some(nidx) = self.names.get(&key) {
+            return *nidx;
+        }
+        let nidx = NameId::from(self.names.len() as u32 + 1);
+        self.env
+            .add_name(nidx, item.clone(), parent)
+            .expect("Invalid name");
+        self.names.insert(key, nidx);
+        nidx
+    }
+}
+
+/*
+ * Copies items of one environment into a destination, remembering where
+ * each went. Terms are copied with explicit stacks, as they can be nested
+ * far deeper than the call stack allows.
+ */
+pub(crate) struct Copier<'a> {
+    src: &'a Environment,
+    dest: &'a mut Destination,
+    names: HashMap<NameId, NameId>,
+    levels: HashMap<LevelId, LevelId>,
+    exprs: HashMap<ExprId, ExprId>,
+    // definitions copied as axioms
+    axioms: HashSet<NameId>,
+}
+
+impl<'a> Copier<'a> {
+    pub(crate) fn new(src: &'a Environment, dest: &'a mut Destination, axioms: &[NameId]) -> Self {
+        Self {
+            src,
+            dest,
+            names: HashMap::from([(NameId::from(0), NameId::from(0))]),
+            levels: HashMap::from([(LevelId::from(0), LevelId::from(0))]),
+            exprs: HashMap::new(),
+            axioms: axioms.iter().copied().collect(),
+        }
+    }
+
+    // A name, after the prefixes it extends
+    pub(crate) fn name(&mut self, nidx: NameId) -> NameId {
+        let mut missing = vec![];
+        let mut n = nidx;
+        while !self.names.contains_key(&n) {
+            missing.push(n);
+            n = self.src.name(n).1;
+        }
+        for n in missing.into_iter().rev() {
+            let (item, parent) = self.src.name(n);
+            let idx = self.dest.name(item, self.names[&parent]);
+            self.names.insert(n, idx);
+        }
+        self.names[&nidx]
+    }
+
+    fn names(&mut self, nidxs: &[NameId]) -> Vec<NameId> {
+        nidxs.iter().map(|n| self.name(*n)).collect()
+    }
+
+    fn level(&mut self, uidx: LevelId) -> LevelId {
+        // Levels whose children have been copied are popped a second time
+        let mut stack = vec![(uidx, false)];
+        while let Some((u, ready)) = stack.pop() {
+            if self.levels.contains_key(&u) {
+                continue;
+            }
+            let level = *self.src.level(u);
+            if !ready {
+                stack.push((u, true));
+                match level {
+                    Level::Succ(u1) => stack.push((u1, false)),
+                    Level::Max(u1, u2) | Level::IMax(u1, u2) => {
+                        stack.extend([(u2, false), (u1, false)])
+                    }
+                    Level::Zero | Level::Param(_) => {}
+                }
+                continue;
+            }
+            let copy = match level {
+                Level::Zero => Level::Zero,
+                Level::Succ(u1) => Level::Succ(self.levels[&u1]),
+                Level::Max(u1, u2) => Level::Max(self.levels[&u1], self.levels[&u2]),
+                Level::IMax(u1, u2) => Level::IMax(self.levels[&u1], self.levels[&u2]),
+                Level::Param(n) => Level::Param(self.name(n)),
+            };
+            let copy = self.dest.env.push_level(copy);
+            self.levels.insert(u, copy);
+        }
+        self.levels[&uidx]
+    }
+
+    fn expr(&mut self, eidx: ExprId) -> ExprId {
+        let mut stack = vec![(eidx, false)];
+        while let Some((e, ready)) = stack.pop() {
+            if self.exprs.contains_key(&e) {
+                continue;
+            }
+            let expr = self.src.expr(e);
+            if !ready {
+                stack.push((e, true));
+                stack.extend(expr.children().into_iter().rev().map(|c| (c, false)));
+                continue;
+            }
+            let copy = match expr {
+                Expr::BoundVar(i) => Expr::BoundVar(*i),
+                Expr::Sort(u) => Expr::Sort(self.level(*u)),
+                Expr::Constant(n, us) => {
+                    if let Some(Constant::Quot(_)) = self.src.resolve_constant(*n) {
+                        self.quot();
+                    }
+                    let us = us.iter().map(|u| self.level(*u)).collect();
+                    Expr::Constant(self.name(*n), us)
+                }
+                Expr::FunAppl(e1, e2) => Expr::FunAppl(self.exprs[e1], self.exprs[e2]),
+                Expr::Lambda(info, n, e1, e2) => {
+                    Expr::Lambda(*info, self.name(*n), self.exprs[e1], self.exprs[e2])
+                }
+                Expr::Pi(info, n, e1, e2) => {
+                    Expr::Pi(*info, self.name(*n), self.exprs[e1], self.exprs[e2])
+                }
+                Expr::Let(n, e1, e2, e3) => Expr::Let(
+                    self.name(*n),
+                    self.exprs[e1],
+                    self.exprs[e2],
+                    self.exprs[e3],
+                ),
+                Expr::Proj(n, field, e1) => Expr::Proj(self.name(*n), *field, self.exprs[e1]),
+                Expr::NatLit(digits) => Expr::NatLit(digits.clone()),
+                Expr::StrLit(s) => Expr::StrLit(s.clone()),
+            };
+            let copy = self.dest.env.push_expr(copy);
+            self.exprs.insert(e, copy);
+        }
+        self.exprs[&eidx]
+    }
+
+    pub(crate) fn quot(&mut self) {
+        if !self.dest.env.has_quot() {
+            self.dest.env.add_quot().expect("Duplicate #QUOT");
+        }
+    }
+
+    // Declarations of the source the copy of a declaration refers to
+    pub(crate) fn dependencies(&self, nidx: NameId) -> Vec<NameId> {
+        match self.src.decl(nidx) {
+            Some(Decl::Def(eidx, _, _)) if self.axioms.contains(&nidx) => {
+                expr_dependencies(self.src, nidx, vec![*eidx])
+            }
+            _ => direct_dependencies(self.src, nidx),
+        }
+    }
+
+    fn copy_decl(&mut self, nidx: NameId) -> Decl {
+        match self.src.decl(nidx).expect("Declaration not found") {
+            Decl::Def(eidx1, eidx2, level_names) if !self.axioms.contains(&nidx) => Decl::Def(
+                self.expr(*eidx1),
+                self.expr(*eidx2),
+                self.names(level_names),
+            ),
+            Decl::Axiom(eidx, level_names) | Decl::Def(eidx, _, level_names) => {
+                Decl::Axiom(self.expr(*eidx), self.names(level_names))
+            }
+            Decl::Ind(params, eidx, intros, level_names) => {
+                let eidx = self.expr(*eidx);
+                let intros = intros
+                    .iter()
+                    .map(|(ni, ei)| (self.name(*ni), self.expr(*ei)))
+                    .collect();
+                Decl::Ind(*params, eidx, intros, self.names(level_names))
+            }
+        }
+    }
+
+    /*
+     * Copy a declaration. A declaration of the same name may already be in
+     * the destination only if it is identical, which since items are shared
+     * means equal up to indices.
+     */
+    pub(crate) fn decl(&mut self, nidx: NameId) -> EnvResult<()> {
+        let name = self.name(nidx);
+        let decl = self.copy_decl(nidx);
+        let env = &mut self.dest.env;
+        match env.decl(name) {
+            Some(existing) if *existing == decl => Ok(()),
+            Some(_) => Err(EnvError::ConflictingDecl(env.name_to_string(name))),
+            None => match decl {
+                Decl::Axiom(eidx, level_names) => env.add_axiom(name, eidx, level_names),
+                Decl::Def(eidx1, eidx2, level_names) => {
+                    env.add_definition(name, eidx1, eidx2, level_names)
+                }
+                Decl::Ind(params, eidx, intros, level_names) => {
+                    env.add_inductive(params, name, eidx, intros, level_names)
+                }
+            },
+        }
+    }
+}
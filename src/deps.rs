@@ -0,0 +1,139 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+
+use super::environment::{Environment, Expr, ExprId, NameId};
+
+/*
+ * Dependencies between declarations. A reference to a constructor or a
+ * recursor counts as a reference to its inductive type; the constants
+ * introduced by #QUOT belong to no declaration.
+ */
+
+fn sort_by_position(env: &Environment, nidxs: &mut [NameId]) {
+    nidxs.sort_by_key(|n| env.decl_position(*n));
+}
+
+// Declarations other than `nidx` referred to by some expressions
+pub(crate) fn expr_dependencies(
+    env: &Environment,
+    nidx: NameId,
+    exprs: Vec<ExprId>,
+) -> Vec<NameId> {
+    let mut visited = HashSet::new();
+    let mut deps = HashSet::new();
+    let mut stack = exprs;
+    while let Some(eidx) = stack.pop() {
+        if !visited.insert(eidx) {
+            continue;
+        }
+        let expr = env.expr(eidx);
+        if let Expr::Constant(n, _) = expr {
+            if let Some(d) = env.constant_decl(*n) {
+                if d != nidx {
+                    deps.insert(d);
+                }
+            }
+        }
+        stack.extend(expr.children());
+    }
+    let mut deps = deps.into_iter().collect::<Vec<_>>();
+    sort_by_position(env, &mut deps);
+    deps
+}
+
+// Declarations referred to by the type, value or constructors of a declaration
+pub fn direct_dependencies(env: &Environment, nidx: NameId) -> Vec<NameId> {
+    let decl = env.decl(nidx).expect("Declaration not found");
+    expr_dependencies(env, nidx, decl.exprs())
+}
+
+// The given declarations and all they depend on, in export order
+pub fn dependency_closure(env: &Environment, roots: &[NameId]) -> Vec<NameId> {
+    closure_with(env, roots, |n| direct_dependencies(env, n))
+}
+
+// Closure of the roots under the given direct dependencies, in export order
+pub(crate) fn closure_with(
+    env: &Environment,
+    roots: &[NameId],
+    mut direct: impl FnMut(NameId) -> Vec<NameId>,
+) -> Vec<NameId> {
+    let mut visited = roots.iter().copied().collect::<HashSet<_>>();
+    let mut stack = roots.to_vec();
+    while let Some(n) = stack.pop() {
+        for d in direct(n) {
+            if visited.insert(d) {
+                stack.push(d);
+            }
+        }
+    }
+    let mut closure = visited.into_iter().collect::<Vec<_>>();
+    sort_by_position(env, &mut closure);
+    closure
+}
+
+// All declarations a declaration depends on, in export order
+pub fn transitive_dependencies(env: &Environment, nidx: NameId) -> Vec<NameId> {
+    let mut deps = dependency_closure(env, &[nidx]);
+    deps.retain(|d| *d != nidx);
+    deps
+}
+
+// A shortest chain of direct dependencies leading from `from` to `to`
+pub fn dependency_chain(env: &Environment, from: NameId, to: NameId) -> Option<Vec<NameId>> {
+    let mut parents = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(n) = queue.pop_front() {
+        if n == to {
+            let mut chain = vec![to];
+            let mut n = to;
+            while n != from {
+                n = parents[&n];
+                chain.push(n);
+            }
+            chain.reverse();
+            return Some(chain);
+        }
+        for d in direct_dependencies(env, n) {
+            if let Entry::Vacant(e) = parents.entry(d) {
+                e.insert(n);
+                queue.push_back(d);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_lines, ParseOptions};
+
+    #[test]
+    fn test_dependencies() {
+        /*
+         * axiom a : Prop
+         * def b : Prop := a
+         * def c : Prop := b
+         */
+        let export = "\
+1 #NS 0 a
+2 #NS 0 b
+3 #NS 0 c
+0 #ES 0
+1 #EC 1
+2 #EC 2
+#AX 1 0
+#DEF 2 0 1
+#DEF 3 0 2
+";
+        let env = parse_lines(export.as_bytes(), &ParseOptions::default(), &mut ()).unwrap();
+        let n = |name| env.find_name(name).unwrap();
+        let (a, b, c) = (n("a"), n("b"), n("c"));
+        assert_eq!(direct_dependencies(&env, c), [b]);
+        assert_eq!(transitive_dependencies(&env, c), [a, b]);
+        assert_eq!(transitive_dependencies(&env, a), []);
+        assert_eq!(dependency_closure(&env, &[c, a]), [a, b, c]);
+        assert_eq!(dependency_chain(&env, c, a), Some(vec![c, b, a]));
+        assert_eq!(dependency_chain(&env, a, c), None);
+    }
+}
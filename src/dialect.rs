@@ -0,0 +1,79 @@
+use core::fmt;
+use std::str::FromStr;
+
+/*
+ * The Lean 3 and Lean 4 export formats share most of their commands. Lean 3
+ * exports may additionally contain notation commands (#PREFIX, #INFIX,
+ * #POSTFIX), while Lean 4 exports may contain projections (#EJ) and literals
+ * (#ELN, #ELS). The two also differ in the names of the quotient constants
+ * and of the sorry axiom. Nothing else depends on the dialect: inductive
+ * types and their recursors are read and checked the same way for both.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    Lean3,
+    #[default]
+    Lean4,
+}
+
+impl Dialect {
+    pub fn supports_projections(&self) -> bool {
+        *self == Dialect::Lean4
+    }
+
+    pub fn supports_literals(&self) -> bool {
+        *self == Dialect::Lean4
+    }
+
+    pub fn supports_notation(&self) -> bool {
+        *self == Dialect::Lean3
+    }
+
+    // Constants introduced by #QUOT and their number of universe parameters
+    pub fn quot_constants(&self) -> [(&'static str, usize); 4] {
+        match self {
+            Dialect::Lean3 => [
+                ("quot", 1),
+                ("quot.mk", 1),
+                ("quot.lift", 2),
+                ("quot.ind", 1),
+            ],
+            Dialect::Lean4 => [
+                ("Quot", 1),
+                ("Quot.mk", 1),
+                ("Quot.lift", 2),
+                ("Quot.ind", 1),
+            ],
+        }
+    }
+
+    // The axiom standing in for missing proofs
+    pub fn sorry_axiom(&self) -> &'static str {
+        match self {
+            Dialect::Lean3 => "sorry_ax",
+            Dialect::Lean4 => "sorryAx",
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::Lean3 => "lean3".fmt(f),
+            Dialect::Lean4 => "lean4".fmt(f),
+        }
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lean3" => Ok(Dialect::Lean3),
+            "lean4" => Ok(Dialect::Lean4),
+            _ => Err(format!("Unknown dialect '{}' (expected lean3 or lean4)", s)),
+        }
+    }
+}
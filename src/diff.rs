@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+
+use super::environment::{Decl, Environment, Expr, ExprId, Level, LevelId, NameId};
+
+/*
+ * How a declaration differs between two environments, matched by name. The
+ * value of an inductive type is its number of parameters and its
+ * constructors; a declaration whose kind changes has a changed value.
+ */
+#[derive(Debug, PartialEq)]
+pub enum DeclChange {
+    Added(String),
+    Removed(String),
+    Changed {
+        name: String,
+        type_changed: bool,
+        value_changed: bool,
+    },
+}
+
+/*
+ * Structural comparison of items of two environments. Expressions are
+ * compared up to alpha-equivalence: binder names are ignored, as are
+ * universe parameter names, which are matched by position in the
+ * declarations being compared. Binder annotations are compared.
+ */
+struct Comparison<'a> {
+    old: &'a Environment,
+    new: &'a Environment,
+    old_params: &'a [NameId],
+    new_params: &'a [NameId],
+    // pairs found equal or being compared
+    visited: HashSet<(ExprId, ExprId)>,
+}
+
+impl Comparison<'_> {
+    fn names_eq(&self, mut n1: NameId, mut n2: NameId) -> bool {
+        let anonymous = NameId::from(0);
+        while n1 != anonymous && n2 != anonymous {
+            let ((item1, parent1), (item2, parent2)) = (self.old.name(n1), self.new.name(n2));
+            if item1 != item2 {
+                return false;
+            }
+            (n1, n2) = (parent1, parent2);
+        }
+        n1 == anonymous && n2 == anonymous
+    }
+
+    fn levels_eq(&self, u1: LevelId, u2: LevelId) -> bool {
+        let mut stack = vec![(u1, u2)];
+        while let Some((u1, u2)) = stack.pop() {
+            match (self.old.level(u1), self.new.level(u2)) {
+                (Level::Zero, Level::Zero) => {}
+                (Level::Succ(v1), Level::Succ(v2)) => stack.push((*v1, *v2)),
+                (Level::Max(v1, w1), Level::Max(v2, w2))
+                | (Level::IMax(v1, w1), Level::IMax(v2, w2)) => {
+                    stack.extend([(*v1, *v2), (*w1, *w2)])
+                }
+                (Level::Param(n1), Level::Param(n2)) => {
+                    let p1 = self.old_params.iter().position(|p| p == n1);
+                    let p2 = self.new_params.iter().position(|p| p == n2);
+                    let eq = match (p1, p2) {
+                        (None, None) => self.names_eq(*n1, *n2),
+                        _ => p1 == p2,
+                    };
+                    if !eq {
+                        return false;
+                    }
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn exprs_eq(&mut self, e1: ExprId, e2: ExprId) -> bool {
+        let mut stack = vec![(e1, e2)];
+        while let Some((e1, e2)) = stack.pop() {
+            // Subterms are shared, so the same pair is often met again
+            if !self.visited.insert((e1, e2)) {
+                continue;
+            }
+            let eq = match (self.old.expr(e1), self.new.expr(e2)) {
+                (Expr::BoundVar(i1), Expr::BoundVar(i2)) => i1 == i2,
+                (Expr::Sort(u1), Expr::Sort(u2)) => self.levels_eq(*u1, *u2),
+                (Expr::Constant(n1, us1), Expr::Constant(n2, us2)) => {
+                    self.names_eq(*n1, *n2)
+                        && us1.len() == us2.len()
+                        && us1.iter().zip(us2).all(|(u1, u2)| self.levels_eq(*u1, *u2))
+                }
+                (Expr::FunAppl(f1, a1), Expr::FunAppl(f2, a2)) => {
+                    stack.extend([(*f1, *f2), (*a1, *a2)]);
+                    true
+                }
+                (Expr::Lambda(i1, _, t1, b1), Expr::Lambda(i2, _, t2, b2))
+                | (Expr::Pi(i1, _, t1, b1), Expr::Pi(i2, _, t2, b2)) => {
+                    stack.extend([(*t1, *t2), (*b1, *b2)]);
+                    i1 == i2
+                }
+                (Expr::Let(_, t1, v1, b1), Expr::Let(_, t2, v2, b2)) => {
+                    stack.extend([(*t1, *t2), (*v1, *v2), (*b1, *b2)]);
+                    true
+                }
+                (Expr::Proj(n1, f1, s1), Expr::Proj(n2, f2, s2)) => {
+                    stack.push((*s1, *s2));
+                    f1 == f2 && self.names_eq(*n1, *n2)
+                }
+                (Expr::NatLit(d1), Expr::NatLit(d2)) => d1 == d2,
+                (Expr::StrLit(s1), Expr::StrLit(s2)) => s1 == s2,
+                _ => false,
+            };
+            if !eq {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Constructors in order, with their names and types
+    fn intros_eq(&mut self, intros1: &[(NameId, ExprId)], intros2: &[(NameId, ExprId)]) -> bool {
+        intros1.len() == intros2.len()
+            && intros1
+                .iter()
+                .zip(intros2)
+                .all(|((n1, e1), (n2, e2))| self.names_eq(*n1, *n2) && self.exprs_eq(*e1, *e2))
+    }
+}
+
+// Whether the type and whether the value of a declaration differ
+fn compare(old: &Environment, new: &Environment, n1: NameId, n2: NameId) -> (bool, bool) {
+    let (d1, d2) = (
+        old.decl(n1).expect("Declaration not found"),
+        new.decl(n2).expect("Declaration not found"),
+    );
+    let mut cmp = Comparison {
+        old,
+        new,
+        old_params: d1.level_params(),
+        new_params: d2.level_params(),
+        visited: HashSet::new(),
+    };
+    let type_of = |d: &Decl| match d {
+        Decl::Axiom(eidx, _) | Decl::Def(eidx, _, _) | Decl::Ind(_, eidx, _, _) => *eidx,
+    };
+    let type_eq =
+        cmp.old_params.len() == cmp.new_params.len() && cmp.exprs_eq(type_of(d1), type_of(d2));
+    // A failed comparison leaves pairs that were never shown equal
+    if !type_eq {
+        cmp.visited.clear();
+    }
+    let value_eq = match (d1, d2) {
+        (Decl::Axiom(..), Decl::Axiom(..)) => true,
+        (Decl::Def(_, v1, _), Decl::Def(_, v2, _)) => cmp.exprs_eq(*v1, *v2),
+        (Decl::Ind(p1, _, intros1, _), Decl::Ind(p2, _, intros2, _)) => {
+            p1 == p2 && cmp.intros_eq(intros1, intros2)
+        }
+        _ => false,
+    };
+    (!type_eq, !value_eq)
+}
+
+/*
+ * The declarations removed from `old`, in its order, followed by those added
+ * to or changed in `new`, in its order.
+ */
+pub fn diff(old: &Environment, new: &Environment) -> Vec<DeclChange> {
+    let by_name = |env: &Environment| {
+        env.declarations()
+            .iter()
+            .map(|n| (env.name_to_string(*n), *n))
+            .collect::<HashMap<_, _>>()
+    };
+    let (old_decls, new_decls) = (by_name(old), by_name(new));
+    let mut changes = vec![];
+    for n in old.declarations() {
+        let name = old.name_to_string(*n);
+        if !new_decls.contains_key(&name) {
+            changes.push(DeclChange::Removed(name));
+        }
+    }
+    for n2 in new.declarations() {
+        let name = new.name_to_string(*n2);
+        match old_decls.get(&name) {
+            None => changes.push(DeclChange::Added(name)),
+            Some(n1) => {
+                let (type_changed, value_changed) = compare(old, new, *n1, *n2);
+                if type_changed || value_changed {
+                    changes.push(DeclChange::Changed {
+                        name,
+                        type_changed,
+                        value_changed,
+                    });
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{term, Builder};
+
+    #[test]
+    fn test_diff() {
+        let mut b = Builder::new();
+        let ty = term!(b, (pi {A : (Sort u)}, (A -> A)));
+        let val = term!(b, (fun {A : (Sort u)} => (fun a : A => a)));
+        b.def("id", ty, val, &["u"]);
+        let prop = b.prop();
+        b.axiom("p", prop, &[]);
+        b.axiom("q", prop, &[]);
+        let p = b.cnst("p", &[]);
+        b.def("r", prop, p, &[]);
+        let old = b.build();
+
+        // Other binder and universe parameter names, added first
+        let mut b = Builder::new();
+        let prop = b.prop();
+        b.axiom("s", prop, &[]);
+        let ty = term!(b, (pi {B : (Sort v)}, (pi x : B, B)));
+        let val = term!(b, (fun {B : (Sort v)} => (fun b : B => b)));
+        b.def("id", ty, val, &["v"]);
+        let ty = term!(b, Type);
+        b.axiom("p", ty, &[]);
+        let s = b.cnst("s", &[]);
+        b.def("r", prop, s, &[]);
+        let new = b.build();
+
+        assert_eq!(
+            diff(&old, &new),
+            [
+                DeclChange::Removed("q".to_string()),
+                DeclChange::Added("s".to_string()),
+                DeclChange::Changed {
+                    name: "p".to_string(),
+                    type_changed: true,
+                    value_changed: false
+                },
+                DeclChange::Changed {
+                    name: "r".to_string(),
+                    type_changed: false,
+                    value_changed: true
+                },
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+}
@@ -1,16 +1,118 @@
 use core::fmt;
 use std::collections::HashMap;
+use std::fmt::Write;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::num::ParseIntError;
+use std::str::FromStr;
 
-type NameIdx = usize;
-type LevelIdx = usize;
-type ExprIdx = usize;
+use super::dialect::Dialect;
+
+/*
+ * Indices of names, levels and expressions. Export indices are dense and
+ * assigned in order, so they index directly into the vectors of the
+ * environment.
+ */
+macro_rules! define_id {
+    ($id:ident) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $id(u32);
+
+        impl $id {
+            pub fn index(self) -> usize {
+                self.0 as usize
+            }
+
+            fn from_index(idx: usize) -> Self {
+                Self(u32::try_from(idx).expect("Index out of range"))
+            }
+        }
+
+        impl From<u32> for $id {
+            fn from(idx: u32) -> Self {
+                Self(idx)
+            }
+        }
+
+        impl FromStr for $id {
+            type Err = ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse::<u32>().map(Self)
+            }
+        }
+
+        impl fmt::Display for $id {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+define_id!(NameId);
+define_id!(LevelId);
+define_id!(ExprId);
+
+// Vector indexed by an id, with gaps for indices not defined (yet)
+struct Arena<T> {
+    items: Vec<Option<T>>,
+    count: usize,
+}
+
+impl<T> Arena<T> {
+    fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            count: 0,
+        }
+    }
+
+    fn get(&self, idx: usize) -> Option<&T> {
+        self.items.get(idx).and_then(|item| item.as_ref())
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        self.get(idx).is_some()
+    }
+
+    /*
+     * Exports number items densely, apart from the lines skipped after
+     * errors. An index far past the items defined so far is rejected, as
+     * storing it would take memory out of proportion to the input.
+     */
+    fn in_range(&self, idx: usize) -> bool {
+        const MAX_GAP: usize = 1 << 16;
+        idx <= 2 * self.count + MAX_GAP
+    }
+
+    fn insert(&mut self, idx: usize, item: T) {
+        if idx >= self.items.len() {
+            self.items.resize_with(idx + 1, || None);
+        }
+        if self.items[idx].replace(item).is_none() {
+            self.count += 1;
+        }
+    }
+
+    // Index past the last defined item
+    fn end(&self) -> usize {
+        self.items.len()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| item.as_ref().map(|item| (i, item)))
+    }
+}
 
 /*
  * <nidx'> #NS <nidx> <string>
  * <nidx'> #NI <nidx> <integer>
  */
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum NameItem {
     Str(String),
     Int(usize),
@@ -28,7 +130,7 @@ impl fmt::Display for NameItem {
 #[derive(Debug, PartialEq)]
 struct Name {
     item: NameItem,
-    parent: NameIdx, // Special value 0 for item with no parent
+    parent: NameId, // Special value 0 for item with no parent
 }
 
 /*
@@ -38,13 +140,13 @@ struct Name {
  * <uidx'> #UP  <nidx>
  */
 
-#[derive(Debug, PartialEq)]
-enum Level {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
     Zero,
-    Succ(LevelIdx),
-    Max(LevelIdx, LevelIdx),
-    IMax(LevelIdx, LevelIdx),
-    Param(NameIdx),
+    Succ(LevelId),
+    Max(LevelId, LevelId),
+    IMax(LevelId, LevelId),
+    Param(NameId),
 }
 
 /*
@@ -61,6 +163,7 @@ enum Level {
  * <eidx'> #EZ <nidx> <eidx_1> <eidx_2> <eidx_3>
  */
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum InfoAnnotation {
     Default,        // #BD
     Implicit,       // #BI
@@ -69,7 +172,7 @@ pub enum InfoAnnotation {
 }
 
 impl InfoAnnotation {
-    fn to_delims(&self) -> (&'static str, &'static str) {
+    fn to_delims(self) -> (&'static str, &'static str) {
         match self {
             InfoAnnotation::Default => ("(", ")"),
             InfoAnnotation::Implicit => ("{", "}"),
@@ -79,190 +182,926 @@ impl InfoAnnotation {
     }
 }
 
-enum Expr {
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
     BoundVar(usize),
-    Sort(LevelIdx),
-    Constant(NameIdx, Vec<LevelIdx>),
-    FunAppl(ExprIdx, ExprIdx),
-    Lambda(InfoAnnotation, NameIdx, ExprIdx, ExprIdx),
-    Pi(InfoAnnotation, NameIdx, ExprIdx, ExprIdx),
+    Sort(LevelId),
+    Constant(NameId, Vec<LevelId>),
+    FunAppl(ExprId, ExprId),
+    Lambda(InfoAnnotation, NameId, ExprId, ExprId),
+    Pi(InfoAnnotation, NameId, ExprId, ExprId),
+    // name, type, value, body
+    Let(NameId, ExprId, ExprId, ExprId),
+    // structure name, field index, structure value
+    Proj(NameId, usize, ExprId),
+    // decimal digits
+    NatLit(String),
+    StrLit(String),
+}
+
+/*
+ * Metadata of an expression, computed from that of its children when it is
+ * added, so that traversals can skip subterms without visiting them. The hash
+ * is structural: children contribute their hashes rather than their indices.
+ */
+#[derive(Debug, Clone, Copy)]
+struct ExprData {
+    // one past the largest loose bound variable, 0 for a closed term
+    loose_bvar_range: usize,
+    has_level_params: bool,
+    hash: u64,
+}
+
+impl Expr {
+    // Direct subexpressions
+    pub fn children(&self) -> Vec<ExprId> {
+        match self {
+            Expr::BoundVar(_)
+            | Expr::Sort(_)
+            | Expr::Constant(_, _)
+            | Expr::NatLit(_)
+            | Expr::StrLit(_) => vec![],
+            Expr::FunAppl(e1, e2) | Expr::Lambda(_, _, e1, e2) | Expr::Pi(_, _, e1, e2) => {
+                vec![*e1, *e2]
+            }
+            Expr::Let(_, e1, e2, e3) => vec![*e1, *e2, *e3],
+            Expr::Proj(_, _, e) => vec![*e],
+        }
+    }
+}
+
+/*
+ * Pending work of the printer. Terms can be nested far deeper than the call
+ * stack allows, so printing keeps its own stack of items.
+ */
+enum PrintItem {
+    Expr(ExprId),
+    Level(LevelId),
+    Str(&'static str),
+    Text(String),
+    // enter and leave the body of a binder
+    Bind(String),
+    Unbind,
+    // the names of the enclosing binders, for debugging
+    VarStack,
 }
 
 // #AX <nidx> <eidx> <nidx*>
 // #DEF <nidx> <eidx_1> <edix_2> <nidx*>
 // #IND <num> <nidx> <eidx> <num_intros> <intro>* <nidx*>
-enum Decl {
+#[derive(Debug, PartialEq)]
+pub enum Decl {
+    // type, level_names
+    Axiom(ExprId, Vec<NameId>),
     // type, body, level_names
-    Def(ExprIdx, ExprIdx, Vec<NameIdx>),
+    Def(ExprId, ExprId, Vec<NameId>),
     // parameters, name, type, introduction rules, and universe parameters
-    Ind(usize, ExprIdx, Vec<(NameIdx, ExprIdx)>, Vec<NameIdx>),
+    Ind(usize, ExprId, Vec<(NameId, ExprId)>, Vec<NameId>),
+}
+
+impl Decl {
+    pub fn level_params(&self) -> &[NameId] {
+        match self {
+            Decl::Axiom(_, level_names)
+            | Decl::Def(_, _, level_names)
+            | Decl::Ind(_, _, _, level_names) => level_names,
+        }
+    }
+
+    // Type, value and constructor types
+    pub fn exprs(&self) -> Vec<ExprId> {
+        match self {
+            Decl::Axiom(eidx, _) => vec![*eidx],
+            Decl::Def(eidx1, eidx2, _) => vec![*eidx1, *eidx2],
+            Decl::Ind(_, eidx, intros, _) => {
+                let mut exprs = vec![*eidx];
+                exprs.extend(intros.iter().map(|(_, ei)| *ei));
+                exprs
+            }
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Decl::Axiom(..) => "axiom",
+            Decl::Def(..) => "definition",
+            Decl::Ind(..) => "inductive",
+        }
+    }
+}
+
+/*
+ * What a constant refers to. Constructors and recursors of inductive types,
+ * and the constants introduced by #QUOT, are not declarations of their own.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constant {
+    Decl(NameId),
+    // inductive, constructor number
+    Constructor(NameId, usize),
+    // inductive
+    Recursor(NameId),
+    // index into Dialect::quot_constants
+    Quot(usize),
+}
+
+/*
+ * #PREFIX  <nidx> <prec> <token>
+ * #INFIX   <nidx> <prec> <token>
+ * #POSTFIX <nidx> <prec> <token>
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotationKind {
+    Prefix,
+    Infix,
+    Postfix,
+}
+
+#[derive(PartialEq)]
+struct Notation {
+    kind: NotationKind,
+    name: NameId,
+    prec: usize,
+    token: String,
+}
+
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum EnvError {
+    UnknownName(NameId),
+    UnknownLevel(LevelId),
+    UnknownExpr(ExprId),
+    DuplicateName(NameId),
+    DuplicateLevel(LevelId),
+    DuplicateExpr(ExprId),
+    DuplicateDecl(NameId),
+    DuplicateQuot,
+    // Index of a kind of item far past those defined
+    IndexTooLarge(&'static str, u32),
+    // De Bruijn index beyond any depth terms can be nested to
+    BoundVarTooLarge(usize),
+    Unsupported(&'static str, Dialect),
+    // Declarations of the same name that differ, when merging exports
+    ConflictingDecl(String),
 }
 
+impl fmt::Display for EnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvError::UnknownName(idx) => write!(f, "Unknown name index {}", idx),
+            EnvError::UnknownLevel(idx) => write!(f, "Unknown level index {}", idx),
+            EnvError::UnknownExpr(idx) => write!(f, "Unknown expression index {}", idx),
+            EnvError::DuplicateName(idx) => write!(f, "Duplicate name index {}", idx),
+            EnvError::DuplicateLevel(idx) => write!(f, "Duplicate level index {}", idx),
+            EnvError::DuplicateExpr(idx) => write!(f, "Duplicate expression index {}", idx),
+            EnvError::DuplicateDecl(idx) => {
+                write!(f, "Duplicate declaration of name index {}", idx)
+            }
+            EnvError::DuplicateQuot => write!(f, "Duplicate #QUOT"),
+            EnvError::IndexTooLarge(kind, idx) => write!(f, "{} index {} is too large", kind, idx),
+            EnvError::BoundVarTooLarge(i) => write!(f, "Bound variable {} is too large", i),
+            EnvError::Unsupported(what, dialect) => {
+                write!(f, "{} are not supported in the {} dialect", what, dialect)
+            }
+            EnvError::ConflictingDecl(name) => {
+                write!(f, "Conflicting declarations of {}", name)
+            }
+        }
+    }
+}
+
+pub type EnvResult<T> = Result<T, EnvError>;
+
 pub struct Environment {
-    names: HashMap<NameIdx, Name>,
-    levels: HashMap<LevelIdx, Level>,
-    exprs: HashMap<ExprIdx, Expr>,
-    decls: HashMap<NameIdx, Decl>,
+    dialect: Dialect,
+    names: Arena<Name>,
+    // levels with whether they contain a parameter
+    levels: Arena<(Level, bool)>,
+    exprs: Arena<(Expr, ExprData)>,
+    // declarations by name, with their position in the export
+    decls: Arena<(usize, Decl)>,
+    // declarations in the order they were added
+    decl_order: Vec<NameId>,
+    // constructor to inductive
+    constructors: HashMap<NameId, NameId>,
+    notations: Vec<Notation>,
+    quot: bool,
+    // hash to the last interned expression with that hash, and from
+    // there to the previous one with the same hash
+    interned: HashMap<u64, ExprId>,
+    hash_collisions: HashMap<ExprId, ExprId>,
+    // export indices of expressions equal to an earlier one
+    expr_aliases: HashMap<ExprId, ExprId>,
+    // levels are interned the same way, without hashing them first
+    interned_levels: HashMap<Level, LevelId>,
+    level_aliases: HashMap<LevelId, LevelId>,
     show_var_stack: bool,
 }
 
+/*
+ * Environments are equal if they hold the same items at the same indices.
+ * What is computed from the items, such as interning and the metadata of
+ * expressions, is not compared.
+ */
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        self.dialect == other.dialect
+            && self.names.iter().eq(other.names.iter())
+            && self.levels().eq(other.levels())
+            && self.exprs().eq(other.exprs())
+            && self.decls.iter().eq(other.decls.iter())
+            && self.decl_order == other.decl_order
+            && self.notations == other.notations
+            && self.quot == other.quot
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
-        let mut levels = HashMap::new();
-        levels.insert(0, Level::Zero);
+        Self::with_dialect(Dialect::default())
+    }
+
+    pub fn with_dialect(dialect: Dialect) -> Self {
+        let mut levels = Arena::new();
+        levels.insert(0, (Level::Zero, false));
         Self {
-            names: HashMap::new(),
+            dialect,
+            names: Arena::new(),
             levels,
-            exprs: HashMap::new(),
-            decls: HashMap::new(),
+            exprs: Arena::new(),
+            decls: Arena::new(),
+            decl_order: Vec::new(),
+            constructors: HashMap::new(),
+            notations: Vec::new(),
+            quot: false,
+            interned: HashMap::new(),
+            hash_collisions: HashMap::new(),
+            expr_aliases: HashMap::new(),
+            interned_levels: HashMap::from([(Level::Zero, LevelId::from(0))]),
+            level_aliases: HashMap::new(),
             show_var_stack: false,
         }
     }
 
-    fn has_name(&self, idx: NameIdx) {
-        assert!(self.names.contains_key(&idx));
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    // Index 0 is the anonymous name
+    fn has_name(&self, idx: NameId) -> EnvResult<()> {
+        if idx.0 == 0 || self.names.contains(idx.index()) {
+            Ok(())
+        } else {
+            Err(EnvError::UnknownName(idx))
+        }
+    }
+
+    fn has_level(&self, idx: LevelId) -> EnvResult<()> {
+        if self.levels.contains(idx.index()) {
+            Ok(())
+        } else {
+            Err(EnvError::UnknownLevel(idx))
+        }
+    }
+
+    fn has_expr(&self, idx: ExprId) -> EnvResult<()> {
+        if self.exprs.contains(idx.index()) {
+            Ok(())
+        } else {
+            Err(EnvError::UnknownExpr(idx))
+        }
+    }
+
+    fn new_level(&self, idx: LevelId) -> EnvResult<()> {
+        if self.levels.contains(idx.index()) {
+            Err(EnvError::DuplicateLevel(idx))
+        } else if !self.levels.in_range(idx.index()) {
+            Err(EnvError::IndexTooLarge("Level", idx.0))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn new_expr(&self, idx: ExprId) -> EnvResult<()> {
+        if self.exprs.contains(idx.index()) {
+            Err(EnvError::DuplicateExpr(idx))
+        } else if !self.exprs.in_range(idx.index()) {
+            Err(EnvError::IndexTooLarge("Expression", idx.0))
+        } else {
+            Ok(())
+        }
+    }
+
+    // An expression argument, replaced by the interned equal expression
+    fn expr_arg(&self, idx: ExprId) -> EnvResult<ExprId> {
+        self.has_expr(idx)?;
+        Ok(self.expr_aliases.get(&idx).copied().unwrap_or(idx))
     }
 
-    fn has_level(&self, idx: LevelIdx) {
-        assert!(self.levels.contains_key(&idx));
+    // A level argument, replaced by the interned equal level
+    fn level_arg(&self, idx: LevelId) -> EnvResult<LevelId> {
+        self.has_level(idx)?;
+        Ok(self.level_aliases.get(&idx).copied().unwrap_or(idx))
     }
 
-    fn has_expr(&self, idx: ExprIdx) {
-        assert!(self.exprs.contains_key(&idx));
+    fn insert_level(&mut self, idx: LevelId, level: Level) {
+        let has_params = match level {
+            Level::Zero => false,
+            Level::Succ(u) => self.level_has_params(u),
+            Level::Max(u1, u2) | Level::IMax(u1, u2) => {
+                self.level_has_params(u1) || self.level_has_params(u2)
+            }
+            Level::Param(_) => true,
+        };
+        self.levels.insert(idx.index(), (level, has_params));
     }
 
-    pub fn add_name(&mut self, idx: NameIdx, item: NameItem, parent: NameIdx) {
-        assert!(!self.names.contains_key(&idx));
-        if parent != 0 {
-            self.has_name(parent);
+    // Store a level of the export, as an alias if an equal level exists
+    fn add_level(&mut self, idx: LevelId, level: Level) {
+        match self.interned_levels.get(&level) {
+            Some(u) => {
+                self.level_aliases.insert(idx, *u);
+            }
+            None => {
+                self.interned_levels.insert(level, idx);
+            }
         }
-        self.names.insert(idx, Name { item, parent });
+        self.insert_level(idx, level);
     }
 
-    pub fn add_level_succ(&mut self, uidxp: LevelIdx, uidx: LevelIdx) {
-        assert!(!self.levels.contains_key(&uidxp));
-        self.has_level(uidx);
-        self.levels.insert(uidxp, Level::Succ(uidx));
+    // Metadata of an expression whose children are in the environment
+    fn expr_data(&self, expr: &Expr) -> ExprData {
+        let data = |e: &ExprId| self.exprs.get(e.index()).expect("Expr not found").1;
+        let mut hasher = DefaultHasher::new();
+        std::mem::discriminant(expr).hash(&mut hasher);
+        let mut loose_bvar_range = 0;
+        let mut has_level_params = false;
+        match expr {
+            Expr::BoundVar(i) => {
+                i.hash(&mut hasher);
+                // Saturating, so that no index counts as closed
+                loose_bvar_range = i.saturating_add(1);
+            }
+            Expr::Sort(u) => {
+                u.hash(&mut hasher);
+                has_level_params = self.level_has_params(*u);
+            }
+            Expr::Constant(n, us) => {
+                (n, us).hash(&mut hasher);
+                has_level_params = us.iter().any(|u| self.level_has_params(*u));
+            }
+            Expr::Lambda(info, n, _, _) | Expr::Pi(info, n, _, _) => (info, n).hash(&mut hasher),
+            Expr::Let(n, _, _, _) => n.hash(&mut hasher),
+            Expr::FunAppl(_, _) => {}
+            Expr::Proj(n, field, _) => (n, field).hash(&mut hasher),
+            Expr::NatLit(s) | Expr::StrLit(s) => s.hash(&mut hasher),
+        }
+        // The body of a binder is under one more binder
+        let body = match expr {
+            Expr::Lambda(..) | Expr::Pi(..) => 1,
+            Expr::Let(..) => 2,
+            _ => usize::MAX,
+        };
+        for (i, c) in expr.children().iter().enumerate() {
+            let d = data(c);
+            let range = if i == body {
+                d.loose_bvar_range.saturating_sub(1)
+            } else {
+                d.loose_bvar_range
+            };
+            loose_bvar_range = loose_bvar_range.max(range);
+            has_level_params |= d.has_level_params;
+            d.hash.hash(&mut hasher);
+        }
+        ExprData {
+            loose_bvar_range,
+            has_level_params,
+            hash: hasher.finish(),
+        }
     }
 
-    pub fn add_level_max(&mut self, uidxp: LevelIdx, uidx1: LevelIdx, uidx2: LevelIdx) {
-        assert!(!self.levels.contains_key(&uidxp));
-        self.has_level(uidx1);
-        self.has_level(uidx2);
-        self.levels.insert(uidxp, Level::Max(uidx1, uidx2));
+    // Interned expression equal to `expr`, whose children are interned
+    fn find_interned(&self, expr: &Expr, hash: u64) -> Option<ExprId> {
+        let mut candidate = self.interned.get(&hash).copied();
+        while let Some(c) = candidate {
+            if self.expr(c) == expr {
+                return Some(c);
+            }
+            candidate = self.hash_collisions.get(&c).copied();
+        }
+        None
     }
 
-    pub fn add_level_imax(&mut self, uidxp: LevelIdx, uidx1: LevelIdx, uidx2: LevelIdx) {
-        assert!(!self.levels.contains_key(&uidxp));
-        self.has_level(uidx1);
-        self.has_level(uidx2);
-        self.levels.insert(uidxp, Level::IMax(uidx1, uidx2));
+    fn intern(&mut self, idx: ExprId, hash: u64) {
+        if let Some(h) = self.interned.insert(hash, idx) {
+            self.hash_collisions.insert(idx, h);
+        }
     }
 
-    pub fn add_level_param(&mut self, uidxp: LevelIdx, nidx: NameIdx) {
-        assert!(!self.levels.contains_key(&uidxp));
-        self.has_name(nidx);
-        self.levels.insert(uidxp, Level::Param(nidx));
+    /*
+     * Store an expression of the export. If an equal expression exists, the
+     * index becomes an alias of it, so that every expression reachable from
+     * a declaration is interned.
+     */
+    fn add_expr(&mut self, idx: ExprId, expr: Expr) {
+        let data = self.expr_data(&expr);
+        match self.find_interned(&expr, data.hash) {
+            Some(c) => {
+                self.expr_aliases.insert(idx, c);
+            }
+            None => self.intern(idx, data.hash),
+        }
+        self.exprs.insert(idx.index(), (expr, data));
     }
 
-    pub fn add_expr_sort(&mut self, eidxp: ExprIdx, uidx: LevelIdx) {
-        assert!(!self.exprs.contains_key(&eidxp));
-        self.has_level(uidx);
-        self.exprs.insert(eidxp, Expr::Sort(uidx));
+    fn new_decl(&self, idx: NameId) -> EnvResult<()> {
+        if self.decls.contains(idx.index()) || self.constructors.contains_key(&idx) {
+            Err(EnvError::DuplicateDecl(idx))
+        } else {
+            Ok(())
+        }
     }
 
-    pub fn add_expr_bound_var(&mut self, eidxp: ExprIdx, i: usize) {
-        assert!(!self.exprs.contains_key(&eidxp));
-        self.exprs.insert(eidxp, Expr::BoundVar(i));
+    pub fn add_name(&mut self, idx: NameId, item: NameItem, parent: NameId) -> EnvResult<()> {
+        if idx.0 == 0 || self.names.contains(idx.index()) {
+            return Err(EnvError::DuplicateName(idx));
+        }
+        if !self.names.in_range(idx.index()) {
+            return Err(EnvError::IndexTooLarge("Name", idx.0));
+        }
+        self.has_name(parent)?;
+        self.names.insert(idx.index(), Name { item, parent });
+        Ok(())
+    }
+
+    pub fn add_level_succ(&mut self, uidxp: LevelId, uidx: LevelId) -> EnvResult<()> {
+        self.new_level(uidxp)?;
+        let uidx = self.level_arg(uidx)?;
+        self.add_level(uidxp, Level::Succ(uidx));
+        Ok(())
+    }
+
+    pub fn add_level_max(
+        &mut self,
+        uidxp: LevelId,
+        uidx1: LevelId,
+        uidx2: LevelId,
+    ) -> EnvResult<()> {
+        self.new_level(uidxp)?;
+        let uidx1 = self.level_arg(uidx1)?;
+        let uidx2 = self.level_arg(uidx2)?;
+        self.add_level(uidxp, Level::Max(uidx1, uidx2));
+        Ok(())
+    }
+
+    pub fn add_level_imax(
+        &mut self,
+        uidxp: LevelId,
+        uidx1: LevelId,
+        uidx2: LevelId,
+    ) -> EnvResult<()> {
+        self.new_level(uidxp)?;
+        let uidx1 = self.level_arg(uidx1)?;
+        let uidx2 = self.level_arg(uidx2)?;
+        self.add_level(uidxp, Level::IMax(uidx1, uidx2));
+        Ok(())
+    }
+
+    pub fn add_level_param(&mut self, uidxp: LevelId, nidx: NameId) -> EnvResult<()> {
+        self.new_level(uidxp)?;
+        self.has_name(nidx)?;
+        self.add_level(uidxp, Level::Param(nidx));
+        Ok(())
+    }
+
+    pub fn add_expr_sort(&mut self, eidxp: ExprId, uidx: LevelId) -> EnvResult<()> {
+        self.new_expr(eidxp)?;
+        let uidx = self.level_arg(uidx)?;
+        self.add_expr(eidxp, Expr::Sort(uidx));
+        Ok(())
+    }
+
+    pub fn add_expr_bound_var(&mut self, eidxp: ExprId, i: usize) -> EnvResult<()> {
+        self.new_expr(eidxp)?;
+        // Larger indices could only overflow when terms are lifted
+        if i > u32::MAX as usize {
+            return Err(EnvError::BoundVarTooLarge(i));
+        }
+        self.add_expr(eidxp, Expr::BoundVar(i));
+        Ok(())
     }
 
     pub fn add_expr_pi(
         &mut self,
-        eidxp: ExprIdx,
+        eidxp: ExprId,
         info: InfoAnnotation,
-        nidx: NameIdx,
-        eidx1: ExprIdx,
-        eidx2: ExprIdx,
-    ) {
-        assert!(!self.exprs.contains_key(&eidxp));
-        self.has_name(nidx);
-        self.has_expr(eidx1);
-        self.has_expr(eidx2);
-        self.exprs.insert(eidxp, Expr::Pi(info, nidx, eidx1, eidx2));
+        nidx: NameId,
+        eidx1: ExprId,
+        eidx2: ExprId,
+    ) -> EnvResult<()> {
+        self.new_expr(eidxp)?;
+        self.has_name(nidx)?;
+        let eidx1 = self.expr_arg(eidx1)?;
+        let eidx2 = self.expr_arg(eidx2)?;
+        self.add_expr(eidxp, Expr::Pi(info, nidx, eidx1, eidx2));
+        Ok(())
     }
 
     pub fn add_expr_lambda(
         &mut self,
-        eidxp: ExprIdx,
+        eidxp: ExprId,
         info: InfoAnnotation,
-        nidx: NameIdx,
-        eidx1: ExprIdx,
-        eidx2: ExprIdx,
-    ) {
-        assert!(!self.exprs.contains_key(&eidxp));
-        self.has_name(nidx);
-        self.has_expr(eidx1);
-        self.has_expr(eidx2);
-        self.exprs
-            .insert(eidxp, Expr::Lambda(info, nidx, eidx1, eidx2));
+        nidx: NameId,
+        eidx1: ExprId,
+        eidx2: ExprId,
+    ) -> EnvResult<()> {
+        self.new_expr(eidxp)?;
+        self.has_name(nidx)?;
+        let eidx1 = self.expr_arg(eidx1)?;
+        let eidx2 = self.expr_arg(eidx2)?;
+        self.add_expr(eidxp, Expr::Lambda(info, nidx, eidx1, eidx2));
+        Ok(())
+    }
+
+    pub fn add_expr_constant(
+        &mut self,
+        eidxp: ExprId,
+        nidx: NameId,
+        level_idxs: Vec<LevelId>,
+    ) -> EnvResult<()> {
+        self.new_expr(eidxp)?;
+        self.has_name(nidx)?;
+        let level_idxs = level_idxs
+            .into_iter()
+            .map(|li| self.level_arg(li))
+            .collect::<EnvResult<_>>()?;
+        self.add_expr(eidxp, Expr::Constant(nidx, level_idxs));
+        Ok(())
+    }
+
+    pub fn add_expr_funappl(
+        &mut self,
+        eidxp: ExprId,
+        eidx1: ExprId,
+        eidx2: ExprId,
+    ) -> EnvResult<()> {
+        self.new_expr(eidxp)?;
+        let eidx1 = self.expr_arg(eidx1)?;
+        let eidx2 = self.expr_arg(eidx2)?;
+        self.add_expr(eidxp, Expr::FunAppl(eidx1, eidx2));
+        Ok(())
+    }
+
+    pub fn add_expr_let(
+        &mut self,
+        eidxp: ExprId,
+        nidx: NameId,
+        eidx1: ExprId,
+        eidx2: ExprId,
+        eidx3: ExprId,
+    ) -> EnvResult<()> {
+        self.new_expr(eidxp)?;
+        self.has_name(nidx)?;
+        let eidx1 = self.expr_arg(eidx1)?;
+        let eidx2 = self.expr_arg(eidx2)?;
+        let eidx3 = self.expr_arg(eidx3)?;
+        self.add_expr(eidxp, Expr::Let(nidx, eidx1, eidx2, eidx3));
+        Ok(())
+    }
+
+    pub fn add_expr_proj(
+        &mut self,
+        eidxp: ExprId,
+        nidx: NameId,
+        field: usize,
+        eidx: ExprId,
+    ) -> EnvResult<()> {
+        if !self.dialect.supports_projections() {
+            return Err(EnvError::Unsupported("Projections", self.dialect));
+        }
+        self.new_expr(eidxp)?;
+        self.has_name(nidx)?;
+        let eidx = self.expr_arg(eidx)?;
+        self.add_expr(eidxp, Expr::Proj(nidx, field, eidx));
+        Ok(())
+    }
+
+    pub fn add_expr_nat_lit(&mut self, eidxp: ExprId, digits: String) -> EnvResult<()> {
+        if !self.dialect.supports_literals() {
+            return Err(EnvError::Unsupported("Literals", self.dialect));
+        }
+        self.new_expr(eidxp)?;
+        self.add_expr(eidxp, Expr::NatLit(digits));
+        Ok(())
     }
 
-    pub fn add_expr_constant(&mut self, eidxp: ExprIdx, nidx: NameIdx, level_idxs: Vec<LevelIdx>) {
-        assert!(!self.exprs.contains_key(&eidxp));
-        self.has_name(nidx);
-        level_idxs.iter().for_each(|li| self.has_level(*li));
-        self.exprs.insert(eidxp, Expr::Constant(nidx, level_idxs));
+    pub fn add_expr_str_lit(&mut self, eidxp: ExprId, s: String) -> EnvResult<()> {
+        if !self.dialect.supports_literals() {
+            return Err(EnvError::Unsupported("Literals", self.dialect));
+        }
+        self.new_expr(eidxp)?;
+        self.add_expr(eidxp, Expr::StrLit(s));
+        Ok(())
     }
 
-    pub fn add_expr_funappl(&mut self, eidxp: ExprIdx, eidx1: ExprIdx, eidx2: ExprIdx) {
-        assert!(!self.exprs.contains_key(&eidxp));
-        self.has_expr(eidx1);
-        self.has_expr(eidx2);
-        self.exprs.insert(eidxp, Expr::FunAppl(eidx1, eidx2));
+    // #AX <nidx> <eidx> <nidx*>
+    pub fn add_axiom(
+        &mut self,
+        nidx: NameId,
+        eidx: ExprId,
+        level_names: Vec<NameId>,
+    ) -> EnvResult<()> {
+        self.new_decl(nidx)?;
+        self.has_name(nidx)?;
+        let eidx = self.expr_arg(eidx)?;
+        level_names.iter().try_for_each(|i| self.has_name(*i))?;
+        self.insert_decl(nidx, Decl::Axiom(eidx, level_names));
+        Ok(())
     }
 
     // #DEF <nidx> <eidx_1> <edix_2> <nidx*>
     pub fn add_definition(
         &mut self,
-        nidx: NameIdx,
-        eidx1: ExprIdx,
-        eidx2: ExprIdx,
-        level_names: Vec<NameIdx>,
-    ) {
-        assert!(!self.decls.contains_key(&nidx));
-        self.has_name(nidx);
-        self.has_expr(eidx1);
-        self.has_expr(eidx2);
-        level_names.iter().for_each(|i| self.has_name(*i));
-        self.decls
-            .insert(nidx, Decl::Def(eidx1, eidx2, level_names));
+        nidx: NameId,
+        eidx1: ExprId,
+        eidx2: ExprId,
+        level_names: Vec<NameId>,
+    ) -> EnvResult<()> {
+        self.new_decl(nidx)?;
+        self.has_name(nidx)?;
+        let eidx1 = self.expr_arg(eidx1)?;
+        let eidx2 = self.expr_arg(eidx2)?;
+        level_names.iter().try_for_each(|i| self.has_name(*i))?;
+        self.insert_decl(nidx, Decl::Def(eidx1, eidx2, level_names));
+        Ok(())
     }
 
     // #IND <num> <nidx> <eidx> <num_intros> <intro>* <nidx*>
     pub fn add_inductive(
         &mut self,
         params: usize,
-        nidx: NameIdx,
-        eidx: ExprIdx,
-        intros: Vec<(NameIdx, ExprIdx)>,
-        level_names: Vec<NameIdx>,
-    ) {
-        assert!(!self.decls.contains_key(&nidx));
-        self.has_expr(eidx);
-        intros.iter().for_each(|(ni, ei)| {
-            self.has_name(*ni);
-            self.has_expr(*ei);
-        });
-        level_names.iter().for_each(|i| self.has_name(*i));
+        nidx: NameId,
+        eidx: ExprId,
+        intros: Vec<(NameId, ExprId)>,
+        level_names: Vec<NameId>,
+    ) -> EnvResult<()> {
+        self.new_decl(nidx)?;
+        self.has_name(nidx)?;
+        let eidx = self.expr_arg(eidx)?;
+        let intros = intros
+            .into_iter()
+            .map(|(ni, ei)| {
+                self.has_name(ni)?;
+                self.new_decl(ni)?;
+                Ok((ni, self.expr_arg(ei)?))
+            })
+            .collect::<EnvResult<Vec<_>>>()?;
+        level_names.iter().try_for_each(|i| self.has_name(*i))?;
+        for (ni, _) in &intros {
+            self.constructors.insert(*ni, nidx);
+        }
+        self.insert_decl(nidx, Decl::Ind(params, eidx, intros, level_names));
+        Ok(())
+    }
+
+    fn insert_decl(&mut self, nidx: NameId, decl: Decl) {
         self.decls
-            .insert(nidx, Decl::Ind(params, eidx, intros, level_names));
+            .insert(nidx.index(), (self.decl_order.len(), decl));
+        self.decl_order.push(nidx);
+    }
+
+    // #QUOT
+    pub fn add_quot(&mut self) -> EnvResult<()> {
+        if self.quot {
+            return Err(EnvError::DuplicateQuot);
+        }
+        self.quot = true;
+        Ok(())
+    }
+
+    pub fn add_notation(
+        &mut self,
+        kind: NotationKind,
+        nidx: NameId,
+        prec: usize,
+        token: String,
+    ) -> EnvResult<usize> {
+        if !self.dialect.supports_notation() {
+            return Err(EnvError::Unsupported("Notation", self.dialect));
+        }
+        self.has_name(nidx)?;
+        self.notations.push(Notation {
+            kind,
+            name: nidx,
+            prec,
+            token,
+        });
+        Ok(self.notations.len() - 1)
+    }
+
+    pub fn has_quot(&self) -> bool {
+        self.quot
+    }
+
+    pub fn name_count(&self) -> usize {
+        self.names.count
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.count
+    }
+
+    pub fn expr_count(&self) -> usize {
+        self.exprs.count
+    }
+
+    pub fn notation_count(&self) -> usize {
+        self.notations.len()
+    }
+
+    // Names with their last component and parent, by index
+    pub fn names(&self) -> impl Iterator<Item = (NameId, &NameItem, NameId)> {
+        self.names
+            .iter()
+            .map(|(idx, name)| (NameId::from_index(idx), &name.item, name.parent))
+    }
+
+    // Levels by index, starting with level 0
+    pub fn levels(&self) -> impl Iterator<Item = (LevelId, &Level)> {
+        self.levels
+            .iter()
+            .map(|(idx, (level, _))| (LevelId::from_index(idx), level))
+    }
+
+    // Expressions by index, including those equal to an earlier one
+    pub fn exprs(&self) -> impl Iterator<Item = (ExprId, &Expr)> {
+        self.exprs
+            .iter()
+            .map(|(idx, (expr, _))| (ExprId::from_index(idx), expr))
+    }
+
+    // Kind, name, precedence and token of the notations, in export order
+    pub fn notations(&self) -> impl Iterator<Item = (&NotationKind, NameId, usize, &str)> {
+        self.notations
+            .iter()
+            .map(|n| (&n.kind, n.name, n.prec, n.token.as_str()))
+    }
+
+    // Last component and parent of a name
+    pub fn name(&self, nidx: NameId) -> (&NameItem, NameId) {
+        let name = self.names.get(nidx.index()).expect("Name not found");
+        (&name.item, name.parent)
+    }
+
+    pub fn level(&self, uidx: LevelId) -> &Level {
+        &self.levels.get(uidx.index()).expect("Univ not found").0
+    }
+
+    pub fn expr(&self, eidx: ExprId) -> &Expr {
+        &self.exprs.get(eidx.index()).expect("Expr not found").0
+    }
+
+    pub fn level_has_params(&self, uidx: LevelId) -> bool {
+        self.levels.get(uidx.index()).expect("Univ not found").1
+    }
+
+    // One past the largest loose bound variable of an expression, 0 if closed
+    pub fn loose_bvar_range(&self, eidx: ExprId) -> usize {
+        self.exprs
+            .get(eidx.index())
+            .expect("Expr not found")
+            .1
+            .loose_bvar_range
+    }
+
+    pub fn has_level_params(&self, eidx: ExprId) -> bool {
+        self.exprs
+            .get(eidx.index())
+            .expect("Expr not found")
+            .1
+            .has_level_params
+    }
+
+    pub fn decl(&self, nidx: NameId) -> Option<&Decl> {
+        self.decls.get(nidx.index()).map(|(_, decl)| decl)
+    }
+
+    // Declarations in export order
+    pub fn declarations(&self) -> &[NameId] {
+        &self.decl_order
+    }
+
+    pub fn decl_position(&self, nidx: NameId) -> Option<usize> {
+        self.decls.get(nidx.index()).map(|(position, _)| *position)
+    }
+
+    // Name index of a dotted name such as "Nat.succ"
+    pub fn find_name(&self, name: &str) -> Option<NameId> {
+        self.names
+            .iter()
+            .map(|(idx, _)| NameId::from_index(idx))
+            .find(|idx| self.name_to_string(*idx) == name)
+    }
+
+    // Every name by its dotted form, for looking up many names, where
+    // find_name goes through all of them each time
+    pub fn names_by_string(&self) -> HashMap<String, NameId> {
+        self.names
+            .iter()
+            .map(|(idx, _)| {
+                let nidx = NameId::from_index(idx);
+                (self.name_to_string(nidx), nidx)
+            })
+            .collect()
+    }
+
+    pub fn resolve_constant(&self, nidx: NameId) -> Option<Constant> {
+        if self.decls.contains(nidx.index()) {
+            return Some(Constant::Decl(nidx));
+        }
+        if let Some(ind) = self.constructors.get(&nidx) {
+            if let Some(Decl::Ind(_, _, intros, _)) = self.decl(*ind) {
+                let i = intros.iter().position(|(ni, _)| *ni == nidx)?;
+                return Some(Constant::Constructor(*ind, i));
+            }
+        }
+        let name = self.names.get(nidx.index())?;
+        if let (Some(Decl::Ind(..)), NameItem::Str(s)) = (self.decl(name.parent), &name.item) {
+            if s == "rec" {
+                return Some(Constant::Recursor(name.parent));
+            }
+        }
+        if self.quot {
+            let s = self.name_to_string(nidx);
+            let quot_constants = self.dialect.quot_constants();
+            if let Some(i) = quot_constants.iter().position(|(n, _)| *n == s) {
+                return Some(Constant::Quot(i));
+            }
+        }
+        None
+    }
+
+    // The declaration a constant belongs to, if any
+    pub fn constant_decl(&self, nidx: NameId) -> Option<NameId> {
+        match self.resolve_constant(nidx)? {
+            Constant::Decl(n) | Constant::Constructor(n, _) | Constant::Recursor(n) => Some(n),
+            Constant::Quot(_) => None,
+        }
+    }
+
+    // New expressions and levels created by the kernel get fresh indices.
+    // Levels are interned: a level equal to an existing one gets its index
+    pub fn push_level(&mut self, level: Level) -> LevelId {
+        if let Some(u) = self.interned_levels.get(&level) {
+            return *u;
+        }
+        let uidx = LevelId::from_index(self.levels.end());
+        self.interned_levels.insert(level, uidx);
+        self.insert_level(uidx, level);
+        uidx
+    }
+
+    // Expressions are interned: an expression equal to an existing one
+    // gets its index
+    pub fn push_expr(&mut self, expr: Expr) -> ExprId {
+        let data = self.expr_data(&expr);
+        if let Some(c) = self.find_interned(&expr, data.hash) {
+            return c;
+        }
+        let eidx = ExprId::from_index(self.exprs.end());
+        self.intern(eidx, data.hash);
+        self.exprs.insert(eidx.index(), (expr, data));
+        eidx
+    }
+
+    pub fn notation_to_string(&self, i: usize) -> String {
+        let notation = &self.notations[i];
+        let kind = match notation.kind {
+            NotationKind::Prefix => "prefix",
+            NotationKind::Infix => "infix",
+            NotationKind::Postfix => "postfix",
+        };
+        format!(
+            "{} {}:{} := {}",
+            kind,
+            notation.token,
+            notation.prec,
+            self.name_to_string(notation.name)
+        )
     }
 
-    pub fn name_to_string(&self, name_idx: NameIdx) -> String {
+    pub fn name_to_string(&self, name_idx: NameId) -> String {
         let mut items: Vec<String> = Vec::new();
         let mut idx = name_idx;
-        while idx != 0 {
-            let item = self.names.get(&idx).expect("Name not found");
+        while idx.0 != 0 {
+            let item = self.names.get(idx.index()).expect("Name not found");
             items.push(item.item.to_string());
             idx = item.parent;
         }
@@ -270,97 +1109,146 @@ impl Environment {
         items.join(".")
     }
 
-    pub fn level_to_string(&self, uidx: LevelIdx) -> String {
-        let univ = self.levels.get(&uidx).expect("Univ not found");
-        match univ {
-            Level::Zero => "0".to_string(),
-            Level::Succ(u) => format!("(succ {})", self.level_to_string(*u)),
-            Level::Max(u1, u2) => format!(
-                "(max {} {})",
-                self.level_to_string(*u1),
-                self.level_to_string(*u2)
-            ),
-            Level::IMax(u1, u2) => format!(
-                "(imax {} {})",
-                self.level_to_string(*u1),
-                self.level_to_string(*u2)
-            ),
-            Level::Param(n) => self.name_to_string(*n),
-        }
+    pub fn level_to_string(&self, uidx: LevelId) -> String {
+        self.print(PrintItem::Level(uidx))
     }
 
-    pub fn expr_to_string(&self, eidx: ExprIdx) -> String {
-        let mut var_stack: Vec<String> = vec![];
-        let result = self.expr_to_string_help(eidx, &mut var_stack);
-        assert!(var_stack.is_empty());
-        result
+    pub fn expr_to_string(&self, eidx: ExprId) -> String {
+        self.print(PrintItem::Expr(eidx))
     }
 
-    fn pi_or_lambda_to_string(
-        &self,
-        info: &InfoAnnotation,
-        nidx: NameIdx,
-        eidx1: ExprIdx,
-        eidx2: ExprIdx,
-        var_stack: &mut Vec<String>,
-    ) -> String {
-        let delims = info.to_delims();
-        let var_name = self.name_to_string(nidx);
-        let e1 = self.expr_to_string_help(eidx1, var_stack);
-        var_stack.push(var_name.clone());
-        let e2 = self.expr_to_string_help(eidx2, var_stack);
-        var_stack.pop();
-        let mut result = format!("{}{} : {}{}, {}", delims.0, var_name, e1, delims.1, e2);
-        if self.show_var_stack {
-            result.push_str(&format!(" [{}]", var_stack.join(",")));
-        }
-        result
-    }
-
-    fn expr_to_string_help(&self, eidx: ExprIdx, var_stack: &mut Vec<String>) -> String {
-        let expr = self.exprs.get(&eidx).expect("Expr not found");
-        match expr {
-            Expr::Sort(u) => format!("Sort {}", self.level_to_string(*u)),
-            Expr::BoundVar(i) => {
-                if *i < var_stack.len() {
-                    var_stack[var_stack.len() - 1 - *i].clone()
-                } else {
-                    format!("<{}>", i)
+    // The items an expression prints as, in order
+    fn expr_items(&self, eidx: ExprId, var_stack: &[String]) -> Vec<PrintItem> {
+        use PrintItem::{Bind, Expr as E, Level as L, Str, Text, Unbind};
+        match self.expr(eidx) {
+            Expr::Sort(u) => vec![Str("Sort "), L(*u)],
+            Expr::BoundVar(i) => vec![Text(if *i < var_stack.len() {
+                var_stack[var_stack.len() - 1 - *i].clone()
+            } else {
+                format!("<{}>", i)
+            })],
+            Expr::Pi(info, n, e1, e2) | Expr::Lambda(info, n, e1, e2) => {
+                let delims = info.to_delims();
+                let var_name = self.name_to_string(*n);
+                let mut items = vec![
+                    Text(format!("{}{} : ", delims.0, var_name)),
+                    E(*e1),
+                    Text(format!("{}, ", delims.1)),
+                    Bind(var_name),
+                    E(*e2),
+                    Unbind,
+                ];
+                if self.show_var_stack {
+                    items.push(PrintItem::VarStack);
                 }
-            }
-            Expr::Pi(info, n, i1, i2) => self.pi_or_lambda_to_string(info, *n, *i1, *i2, var_stack),
-            Expr::Lambda(info, n, i1, i2) => {
-                self.pi_or_lambda_to_string(info, *n, *i1, *i2, var_stack)
+                items
             }
             Expr::Constant(n, lvls) => {
                 let name = self.name_to_string(*n);
                 if lvls.is_empty() {
-                    name
-                } else {
-                    format!(
-                        "{}.{{{}}}",
-                        name,
-                        lvls.iter()
-                            .map(|li| self.level_to_string(*li))
-                            .collect::<Vec<_>>()
-                            .join(",")
-                    )
+                    return vec![Text(name)];
+                }
+                let mut items = vec![Text(name), Str(".{")];
+                for (i, li) in lvls.iter().enumerate() {
+                    if i > 0 {
+                        items.push(Str(","));
+                    }
+                    items.push(L(*li));
                 }
+                items.push(Str("}"));
+                items
             }
-            Expr::FunAppl(fe, be) => {
-                let fst = self.expr_to_string_help(*fe, var_stack);
-                let bst = self.expr_to_string_help(*be, var_stack);
-                format!("({} {})", fst, bst)
+            Expr::FunAppl(fe, be) => vec![Str("("), E(*fe), Str(" "), E(*be), Str(")")],
+            Expr::Let(n, te, ve, be) => {
+                let var_name = self.name_to_string(*n);
+                vec![
+                    Text(format!("let {} : ", var_name)),
+                    E(*te),
+                    Str(" := "),
+                    E(*ve),
+                    Str("; "),
+                    Bind(var_name),
+                    E(*be),
+                    Unbind,
+                ]
             }
+            Expr::Proj(n, i, se) => vec![
+                Text(format!("({}.{} ", self.name_to_string(*n), i + 1)),
+                E(*se),
+                Str(")"),
+            ],
+            Expr::NatLit(digits) => vec![Text(digits.clone())],
+            Expr::StrLit(s) => vec![Text(format!("{:?}", s))],
         }
     }
 
+    fn level_items(&self, uidx: LevelId) -> Vec<PrintItem> {
+        use PrintItem::{Level as L, Str, Text};
+        match self.level(uidx) {
+            Level::Zero => vec![Str("0")],
+            Level::Succ(u) => vec![Str("(succ "), L(*u), Str(")")],
+            Level::Max(u1, u2) => vec![Str("(max "), L(*u1), Str(" "), L(*u2), Str(")")],
+            Level::IMax(u1, u2) => vec![Str("(imax "), L(*u1), Str(" "), L(*u2), Str(")")],
+            Level::Param(n) => vec![Text(self.name_to_string(*n))],
+        }
+    }
+
+    fn print(&self, root: PrintItem) -> String {
+        let mut out = String::new();
+        let mut var_stack: Vec<String> = vec![];
+        let mut stack = vec![root];
+        while let Some(item) = stack.pop() {
+            let items = match item {
+                PrintItem::Expr(eidx) => self.expr_items(eidx, &var_stack),
+                PrintItem::Level(uidx) => self.level_items(uidx),
+                PrintItem::Str(s) => {
+                    out.push_str(s);
+                    continue;
+                }
+                PrintItem::Text(s) => {
+                    out.push_str(&s);
+                    continue;
+                }
+                PrintItem::Bind(var_name) => {
+                    var_stack.push(var_name);
+                    continue;
+                }
+                PrintItem::Unbind => {
+                    var_stack.pop();
+                    continue;
+                }
+                PrintItem::VarStack => {
+                    write!(out, " [{}]", var_stack.join(",")).unwrap();
+                    continue;
+                }
+            };
+            stack.extend(items.into_iter().rev());
+        }
+        assert!(var_stack.is_empty());
+        out
+    }
+
+    fn axiom_to_string(&self, name: &String, eidx: ExprId, level_name_idxs: &[NameId]) -> String {
+        let level_names = level_name_idxs
+            .iter()
+            .map(|ni| self.name_to_string(*ni))
+            .collect::<Vec<String>>()
+            .join(",");
+        let level_names_fmt = if level_names.is_empty() {
+            "".to_string()
+        } else {
+            format!(".{{{}}}", level_names)
+        };
+        let type_expr = self.expr_to_string(eidx);
+        format!("axiom {}{} {}", name, level_names_fmt, type_expr)
+    }
+
     fn def_to_string(
         &self,
         name: &String,
-        eidx1: ExprIdx,
-        eidx2: ExprIdx,
-        level_name_idxs: &[NameIdx],
+        eidx1: ExprId,
+        eidx2: ExprId,
+        level_name_idxs: &[NameId],
     ) -> String {
         let level_names = level_name_idxs
             .iter()
@@ -383,9 +1271,9 @@ impl Environment {
     fn ind_to_string(
         &self,
         name: &String,
-        eidx: ExprIdx,
-        intros: &[(NameIdx, ExprIdx)],
-        level_name_idxs: &[LevelIdx],
+        eidx: ExprId,
+        intros: &[(NameId, ExprId)],
+        level_name_idxs: &[NameId],
     ) -> String {
         let type_expr = self.expr_to_string(eidx);
         let level_names = level_name_idxs
@@ -415,10 +1303,11 @@ impl Environment {
         )
     }
 
-    pub fn decl_to_string(&self, nidx: NameIdx) -> String {
-        let decl = self.decls.get(&nidx).expect("Declaration not found");
+    pub fn decl_to_string(&self, nidx: NameId) -> String {
+        let decl = self.decl(nidx).expect("Declaration not found");
         let name = self.name_to_string(nidx);
         match decl {
+            Decl::Axiom(eidx, level_names) => self.axiom_to_string(&name, *eidx, level_names),
             Decl::Def(eidx1, eidx2, level_names) => {
                 self.def_to_string(&name, *eidx1, *eidx2, level_names)
             }
@@ -433,6 +1322,18 @@ impl Environment {
 mod tests {
     use super::*;
 
+    fn n(idx: u32) -> NameId {
+        idx.into()
+    }
+
+    fn u(idx: u32) -> LevelId {
+        idx.into()
+    }
+
+    fn e(idx: u32) -> ExprId {
+        idx.into()
+    }
+
     #[test]
     fn names() {
         let mut env = Environment::new();
@@ -442,14 +1343,48 @@ mod tests {
          * 3 #NI 2 1
          * 4 #NS 3 boo
          */
-        env.add_name(1, NameItem::Str("foo".to_string()), 0);
-        env.add_name(2, NameItem::Str("bla".to_string()), 1);
-        env.add_name(3, NameItem::Int(1), 2);
-        env.add_name(4, NameItem::Str("boo".to_string()), 3);
-        assert_eq!(env.name_to_string(1), "foo");
-        assert_eq!(env.name_to_string(2), "foo.bla");
-        assert_eq!(env.name_to_string(3), "foo.bla.1");
-        assert_eq!(env.name_to_string(4), "foo.bla.1.boo");
+        env.add_name(n(1), NameItem::Str("foo".to_string()), n(0))
+            .unwrap();
+        env.add_name(n(2), NameItem::Str("bla".to_string()), n(1))
+            .unwrap();
+        env.add_name(n(3), NameItem::Int(1), n(2)).unwrap();
+        env.add_name(n(4), NameItem::Str("boo".to_string()), n(3))
+            .unwrap();
+        assert_eq!(env.name_to_string(n(1)), "foo");
+        assert_eq!(env.name_to_string(n(2)), "foo.bla");
+        assert_eq!(env.name_to_string(n(3)), "foo.bla.1");
+        assert_eq!(env.name_to_string(n(4)), "foo.bla.1.boo");
+        let by_string = env.names_by_string();
+        assert_eq!(by_string.len(), 4);
+        assert_eq!(by_string["foo.bla.1"], n(3));
+        assert_eq!(env.find_name("foo.bla.1"), Some(n(3)));
+    }
+
+    #[test]
+    fn sparse_indices() {
+        let mut env = Environment::new();
+        let foo = || NameItem::Str("foo".to_string());
+        assert_eq!(
+            env.add_name(n(4_000_000_000), foo(), n(0)),
+            Err(EnvError::IndexTooLarge("Name", 4_000_000_000))
+        );
+        // Gaps left by skipped lines are fine
+        env.add_name(n(100), foo(), n(0)).unwrap();
+        assert_eq!(
+            env.add_level_succ(u(4_000_000_000), u(0)),
+            Err(EnvError::IndexTooLarge("Level", 4_000_000_000))
+        );
+        assert_eq!(
+            env.add_expr_sort(e(4_000_000_000), u(0)),
+            Err(EnvError::IndexTooLarge("Expression", 4_000_000_000))
+        );
+        assert_eq!(
+            env.add_expr_bound_var(e(1), usize::MAX),
+            Err(EnvError::BoundVarTooLarge(usize::MAX))
+        );
+        // Built directly, the term still has a loose bound variable
+        let bvar = env.push_expr(Expr::BoundVar(usize::MAX));
+        assert_eq!(env.loose_bvar_range(bvar), usize::MAX);
     }
 
     #[test]
@@ -465,14 +1400,147 @@ mod tests {
          * 5 #UM 2 3
          * 6 #UIM 5 4
          */
-        env.add_name(1, NameItem::Str("l1".to_string()), 0);
-        env.add_name(2, NameItem::Str("l2".to_string()), 0);
-        env.add_level_succ(1, 0);
-        env.add_level_succ(2, 1);
-        env.add_level_param(3, 1);
-        env.add_level_param(4, 2);
-        env.add_level_max(5, 2, 3);
-        env.add_level_imax(6, 5, 4);
-        assert_eq!(env.level_to_string(6), "(imax (max (succ (succ 0)) l1) l2)");
+        env.add_name(n(1), NameItem::Str("l1".to_string()), n(0))
+            .unwrap();
+        env.add_name(n(2), NameItem::Str("l2".to_string()), n(0))
+            .unwrap();
+        env.add_level_succ(u(1), u(0)).unwrap();
+        env.add_level_succ(u(2), u(1)).unwrap();
+        env.add_level_param(u(3), n(1)).unwrap();
+        env.add_level_param(u(4), n(2)).unwrap();
+        env.add_level_max(u(5), u(2), u(3)).unwrap();
+        env.add_level_imax(u(6), u(5), u(4)).unwrap();
+        assert_eq!(
+            env.level_to_string(u(6)),
+            "(imax (max (succ (succ 0)) l1) l2)"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut env = Environment::new();
+        env.add_name(n(1), NameItem::Str("foo".to_string()), n(0))
+            .unwrap();
+        assert_eq!(
+            env.add_name(n(1), NameItem::Str("bar".to_string()), n(0)),
+            Err(EnvError::DuplicateName(n(1)))
+        );
+        assert_eq!(
+            env.add_name(n(2), NameItem::Str("bar".to_string()), n(3)),
+            Err(EnvError::UnknownName(n(3)))
+        );
+        assert_eq!(
+            env.add_level_succ(u(1), u(2)),
+            Err(EnvError::UnknownLevel(u(2)))
+        );
+        assert_eq!(
+            env.add_level_succ(u(0), u(0)),
+            Err(EnvError::DuplicateLevel(u(0)))
+        );
+        env.add_expr_sort(e(0), u(0)).unwrap();
+        assert_eq!(
+            env.add_expr_funappl(e(1), e(0), e(5)),
+            Err(EnvError::UnknownExpr(e(5)))
+        );
+        env.add_axiom(n(1), e(0), vec![]).unwrap();
+        assert_eq!(
+            env.add_axiom(n(1), e(0), vec![]),
+            Err(EnvError::DuplicateDecl(n(1)))
+        );
+        assert_eq!(
+            env.add_definition(n(4), e(0), e(0), vec![]),
+            Err(EnvError::UnknownName(n(4)))
+        );
+    }
+
+    #[test]
+    fn arenas() {
+        let mut env = Environment::new();
+        env.add_expr_sort(e(0), u(0)).unwrap();
+        // Indices need not be consecutive
+        env.add_expr_bound_var(e(3), 0).unwrap();
+        assert_eq!(env.expr_count(), 2);
+        assert_eq!(
+            env.add_expr_funappl(e(4), e(0), e(2)),
+            Err(EnvError::UnknownExpr(e(2)))
+        );
+        // Kernel terms are added after the last index of the export
+        assert_eq!(env.push_expr(Expr::BoundVar(1)), e(4));
+        assert_eq!(env.push_level(Level::Succ(u(0))), u(1));
+        assert_eq!(env.expr_count(), 3);
+    }
+
+    #[test]
+    fn interning() {
+        let mut env = Environment::new();
+        env.add_expr_bound_var(e(0), 0).unwrap();
+        env.add_expr_bound_var(e(1), 0).unwrap();
+        env.add_expr_funappl(e(2), e(0), e(1)).unwrap();
+        // Equal expressions of the export are identified
+        assert_eq!(env.expr(e(2)), &Expr::FunAppl(e(0), e(0)));
+        assert_eq!(env.push_expr(Expr::BoundVar(0)), e(0));
+        assert_eq!(env.push_expr(Expr::FunAppl(e(0), e(0))), e(2));
+        assert_eq!(env.push_expr(Expr::FunAppl(e(2), e(0))), e(3));
+        assert_eq!(env.push_expr(Expr::FunAppl(e(2), e(0))), e(3));
+
+        // So are levels, and the sorts and constants built from them
+        env.add_level_succ(u(1), u(0)).unwrap();
+        env.add_level_succ(u(2), u(0)).unwrap();
+        env.add_level_max(u(3), u(2), u(1)).unwrap();
+        assert_eq!(env.level(u(3)), &Level::Max(u(1), u(1)));
+        env.add_expr_sort(e(4), u(1)).unwrap();
+        env.add_expr_sort(e(5), u(2)).unwrap();
+        env.add_expr_funappl(e(6), e(5), e(4)).unwrap();
+        assert_eq!(env.expr(e(6)), &Expr::FunAppl(e(4), e(4)));
+        assert_eq!(env.push_expr(Expr::Sort(u(1))), e(4));
+        assert_eq!(env.push_level(Level::Succ(u(0))), u(1));
+        assert_eq!(env.push_level(Level::Zero), u(0));
+        assert_eq!(env.push_level(Level::Succ(u(1))), u(4));
+    }
+
+    #[test]
+    fn metadata() {
+        let mut env = Environment::new();
+        env.add_name(n(1), NameItem::Str("u".to_string()), n(0))
+            .unwrap();
+        env.add_level_param(u(1), n(1)).unwrap();
+        env.add_level_succ(u(2), u(1)).unwrap();
+        env.add_expr_sort(e(0), u(2)).unwrap();
+        env.add_expr_bound_var(e(1), 2).unwrap();
+        env.add_expr_lambda(e(2), InfoAnnotation::Default, n(0), e(0), e(1))
+            .unwrap();
+        env.add_expr_lambda(e(3), InfoAnnotation::Default, n(0), e(0), e(2))
+            .unwrap();
+        env.add_expr_pi(e(4), InfoAnnotation::Default, n(0), e(1), e(3))
+            .unwrap();
+        assert!(env.level_has_params(u(2)) && !env.level_has_params(u(0)));
+        assert!(env.has_level_params(e(4)));
+        let ranges = (1..5).map(|i| env.loose_bvar_range(e(i)));
+        assert_eq!(ranges.collect::<Vec<_>>(), [3, 2, 1, 3]);
+        env.push_expr(Expr::BoundVar(0));
+        assert!(!env.has_level_params(e(5)));
+        assert_eq!(env.loose_bvar_range(e(5)), 1);
+    }
+
+    #[test]
+    fn deep_terms() {
+        // Printing must not recurse on the depth of a term
+        const DEPTH: usize = 1_000_000;
+        let mut env = Environment::new();
+        let mut uidx = u(0);
+        for _ in 0..DEPTH {
+            uidx = env.push_level(Level::Succ(uidx));
+        }
+        let bvar = env.push_expr(Expr::BoundVar(0));
+        let mut eidx = env.push_expr(Expr::Sort(uidx));
+        for _ in 0..DEPTH {
+            eidx = env.push_expr(Expr::FunAppl(eidx, bvar));
+        }
+        let s = env.expr_to_string(eidx);
+        assert_eq!(
+            s.len(),
+            "Sort 0".len() + DEPTH * ("(succ )".len() + "( <0>)".len())
+        );
+        assert!(s.starts_with("((") && s.ends_with(") <0>) <0>)"));
     }
 }
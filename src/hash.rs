@@ -0,0 +1,379 @@
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use super::deps::direct_dependencies;
+use super::environment::{
+    Decl, Environment, Expr, ExprId, InfoAnnotation, Level, LevelId, NameId, NameItem,
+};
+
+/*
+ * 128-bit FNV-1a. Unlike the hashers of the standard library, its output is
+ * fixed, so hashes can be stored and compared across runs and builds.
+ */
+struct StableHasher(u128);
+
+impl StableHasher {
+    fn new(tag: u8) -> Self {
+        let mut hasher = Self(0x6c62272e07bb014262b821756295c58d);
+        hasher.bytes(&[tag]);
+        hasher
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u128;
+            self.0 = self.0.wrapping_mul(0x0000000001000000000000000000013b);
+        }
+    }
+
+    fn usize(&mut self, n: usize) {
+        self.bytes(&(n as u64).to_le_bytes());
+    }
+
+    // Strings are prefixed with their length so that concatenations differ
+    fn str(&mut self, s: &str) {
+        self.usize(s.len());
+        self.bytes(s.as_bytes());
+    }
+
+    fn hash(&mut self, h: u128) {
+        self.bytes(&h.to_le_bytes());
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeclHash(u128);
+
+impl fmt::Display for DeclHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for DeclHash {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u128::from_str_radix(s, 16).map(Self)
+    }
+}
+
+/*
+ * Content hashes of declarations. The hash of a declaration covers its kind,
+ * name, universe parameters, type, value or constructors, and the hashes of
+ * the declarations it refers to, so it changes whenever anything it depends
+ * on changes. Items are hashed by content, so the hash does not depend on
+ * the indices of the export. Results are memoized like those of AxiomDeps.
+ */
+pub struct DeclHashes<'a> {
+    env: &'a Environment,
+    names: HashMap<NameId, u128>,
+    levels: HashMap<LevelId, u128>,
+    exprs: HashMap<ExprId, u128>,
+    memo: HashMap<NameId, DeclHash>,
+}
+
+impl<'a> DeclHashes<'a> {
+    pub fn new(env: &'a Environment) -> Self {
+        Self {
+            env,
+            names: HashMap::new(),
+            levels: HashMap::new(),
+            exprs: HashMap::new(),
+            memo: HashMap::new(),
+        }
+    }
+
+    fn name(&mut self, nidx: NameId) -> u128 {
+        if nidx == NameId::from(0) {
+            return StableHasher::new(b'N').finish();
+        }
+        if let Some(h) = self.names.get(&nidx) {
+            return *h;
+        }
+        let (item, parent) = self.env.name(nidx);
+        let parent = self.name(parent);
+        let mut hasher = StableHasher::new(b'N');
+        hasher.hash(parent);
+        match item {
+            NameItem::Str(s) => {
+                hasher.bytes(b"s");
+                hasher.str(s);
+            }
+            NameItem::Int(i) => {
+                hasher.bytes(b"i");
+                hasher.usize(*i);
+            }
+        }
+        let h = hasher.finish();
+        self.names.insert(nidx, h);
+        h
+    }
+
+    fn level(&mut self, uidx: LevelId) -> u128 {
+        // Levels whose children have been hashed are popped a second time
+        let mut stack = vec![(uidx, false)];
+        while let Some((u, ready)) = stack.pop() {
+            if self.levels.contains_key(&u) {
+                continue;
+            }
+            let level = *self.env.level(u);
+            if !ready {
+                stack.push((u, true));
+                match level {
+                    Level::Succ(u1) => stack.push((u1, false)),
+                    Level::Max(u1, u2) | Level::IMax(u1, u2) => {
+                        stack.extend([(u1, false), (u2, false)])
+                    }
+                    Level::Zero | Level::Param(_) => {}
+                }
+                continue;
+            }
+            let h = match level {
+                Level::Zero => StableHasher::new(b'0').finish(),
+                Level::Succ(u1) => {
+                    let mut hasher = StableHasher::new(b'S');
+                    hasher.hash(self.levels[&u1]);
+                    hasher.finish()
+                }
+                Level::Max(u1, u2) => self.level_pair(b'M', u1, u2),
+                Level::IMax(u1, u2) => self.level_pair(b'I', u1, u2),
+                Level::Param(n) => {
+                    let mut hasher = StableHasher::new(b'P');
+                    hasher.hash(self.name(n));
+                    hasher.finish()
+                }
+            };
+            self.levels.insert(u, h);
+        }
+        self.levels[&uidx]
+    }
+
+    fn level_pair(&self, tag: u8, u1: LevelId, u2: LevelId) -> u128 {
+        let mut hasher = StableHasher::new(tag);
+        hasher.hash(self.levels[&u1]);
+        hasher.hash(self.levels[&u2]);
+        hasher.finish()
+    }
+
+    // Children are hashed after this
+    fn binder(&mut self, tag: u8, info: InfoAnnotation, nidx: NameId) -> StableHasher {
+        let mut hasher = StableHasher::new(tag);
+        hasher.bytes(match info {
+            InfoAnnotation::Default => b"D",
+            InfoAnnotation::Implicit => b"I",
+            InfoAnnotation::StrictImplicit => b"S",
+            InfoAnnotation::InstImplicit => b"C",
+        });
+        hasher.hash(self.name(nidx));
+        hasher
+    }
+
+    fn expr(&mut self, eidx: ExprId) -> u128 {
+        let mut stack = vec![(eidx, false)];
+        while let Some((e, ready)) = stack.pop() {
+            if self.exprs.contains_key(&e) {
+                continue;
+            }
+            let expr = self.env.expr(e);
+            if !ready {
+                stack.push((e, true));
+                stack.extend(expr.children().into_iter().map(|c| (c, false)));
+                continue;
+            }
+            let mut hasher = match expr {
+                Expr::BoundVar(i) => {
+                    let mut hasher = StableHasher::new(b'V');
+                    hasher.usize(*i);
+                    hasher
+                }
+                Expr::Sort(u) => {
+                    let mut hasher = StableHasher::new(b'S');
+                    hasher.hash(self.level(*u));
+                    hasher
+                }
+                Expr::Constant(n, us) => {
+                    let mut hasher = StableHasher::new(b'C');
+                    hasher.hash(self.name(*n));
+                    hasher.usize(us.len());
+                    for u in us {
+                        hasher.hash(self.level(*u));
+                    }
+                    hasher
+                }
+                Expr::FunAppl(..) => StableHasher::new(b'A'),
+                Expr::Lambda(info, n, _, _) => self.binder(b'L', *info, *n),
+                Expr::Pi(info, n, _, _) => self.binder(b'P', *info, *n),
+                Expr::Let(n, _, _, _) => {
+                    let mut hasher = StableHasher::new(b'Z');
+                    hasher.hash(self.name(*n));
+                    hasher
+                }
+                Expr::Proj(n, field, _) => {
+                    let mut hasher = StableHasher::new(b'J');
+                    hasher.hash(self.name(*n));
+                    hasher.usize(*field);
+                    hasher
+                }
+                Expr::NatLit(digits) => {
+                    let mut hasher = StableHasher::new(b'N');
+                    hasher.str(digits);
+                    hasher
+                }
+                Expr::StrLit(s) => {
+                    let mut hasher = StableHasher::new(b'T');
+                    hasher.str(s);
+                    hasher
+                }
+            };
+            for c in expr.children() {
+                hasher.hash(self.exprs[&c]);
+            }
+            self.exprs.insert(e, hasher.finish());
+        }
+        self.exprs[&eidx]
+    }
+
+    // Hash of a declaration, leaving out those of its dependencies
+    fn content(&mut self, nidx: NameId) -> u128 {
+        let decl = self.env.decl(nidx).expect("Declaration not found");
+        let mut hasher = StableHasher::new(decl.kind().as_bytes()[0]);
+        hasher.hash(self.name(nidx));
+        let level_params = decl.level_params();
+        hasher.usize(level_params.len());
+        for n in level_params {
+            hasher.hash(self.name(*n));
+        }
+        match decl {
+            Decl::Axiom(eidx, _) => hasher.hash(self.expr(*eidx)),
+            Decl::Def(eidx1, eidx2, _) => {
+                hasher.hash(self.expr(*eidx1));
+                hasher.hash(self.expr(*eidx2));
+            }
+            Decl::Ind(params, eidx, intros, _) => {
+                hasher.usize(*params);
+                hasher.hash(self.expr(*eidx));
+                hasher.usize(intros.len());
+                for (ni, ei) in intros {
+                    hasher.hash(self.name(*ni));
+                    hasher.hash(self.expr(*ei));
+                }
+            }
+        }
+        hasher.finish()
+    }
+
+    pub fn hash(&mut self, nidx: NameId) -> DeclHash {
+        // Post-order walk so that the dependencies are known first
+        let mut stack = vec![(nidx, None)];
+        let mut in_progress = HashSet::new();
+        while let Some((n, deps)) = stack.pop() {
+            if self.memo.contains_key(&n) {
+                continue;
+            }
+            let deps: Vec<NameId> = match deps {
+                Some(deps) => deps,
+                None => {
+                    let deps = direct_dependencies(self.env, n);
+                    in_progress.insert(n);
+                    let pending = deps
+                        .iter()
+                        .filter(|d| !in_progress.contains(*d))
+                        .map(|d| (*d, None))
+                        .collect::<Vec<_>>();
+                    stack.push((n, Some(deps)));
+                    stack.extend(pending);
+                    continue;
+                }
+            };
+            // Dependencies by name, so that their order in the export does
+            // not matter. One still in progress is part of a cycle, which a
+            // well-formed export does not have, and only counts by name.
+            let mut dep_hashes = deps
+                .iter()
+                .map(|d| {
+                    let h = self.memo.get(d).map_or(0, |h| h.0);
+                    (self.name(*d), h)
+                })
+                .collect::<Vec<_>>();
+            dep_hashes.sort();
+            let mut hasher = StableHasher::new(b'D');
+            hasher.hash(self.content(n));
+            hasher.usize(dep_hashes.len());
+            for (name, h) in dep_hashes {
+                hasher.hash(name);
+                hasher.hash(h);
+            }
+            in_progress.remove(&n);
+            self.memo.insert(n, DeclHash(hasher.finish()));
+        }
+        self.memo[&nidx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::{parse_lines, slice, term, Builder, ParseOptions};
+
+    #[test]
+    fn test_index_independent() {
+        let file = File::open("examples/Nat.gcd_self.export").unwrap();
+        let env = parse_lines(file, &ParseOptions::default(), &mut ()).unwrap();
+        let target = env.find_name("Nat.mod").unwrap();
+        // Slicing renumbers every item
+        let sliced = slice(&env, &[target]);
+        let (mut hashes, mut sliced_hashes) = (DeclHashes::new(&env), DeclHashes::new(&sliced));
+        for n in sliced.declarations() {
+            let name = sliced.name_to_string(*n);
+            let m = env.find_name(&name).unwrap();
+            assert_eq!(sliced_hashes.hash(*n), hashes.hash(m), "{}", name);
+        }
+        let gcd = env.find_name("Nat.gcd").unwrap();
+        assert_ne!(hashes.hash(gcd), hashes.hash(target));
+    }
+
+    #[test]
+    fn test_dependencies() {
+        // p, q := p and r := q, where q is the only difference
+        let build = |q_value: &str| {
+            let mut b = Builder::new();
+            let prop = b.prop();
+            b.axiom("p", prop, &[]);
+            b.axiom("p'", prop, &[]);
+            let value = b.cnst(q_value, &[]);
+            b.def("q", prop, value, &[]);
+            let q = term!(b, "q");
+            b.def("r", prop, q, &[]);
+            let env = b.build();
+            let mut hashes = DeclHashes::new(&env);
+            let names = ["p", "q", "r"].map(|n| env.find_name(n).unwrap());
+            names.map(|n| hashes.hash(n))
+        };
+        let [p1, q1, r1] = build("p");
+        let [p2, q2, r2] = build("p'");
+        assert_eq!(p1, p2);
+        assert_ne!(q1, q2);
+        assert_ne!(r1, r2);
+        assert_eq!(build("p"), [p1, q1, r1]);
+    }
+
+    #[test]
+    fn test_stable() {
+        // Changing the hash invalidates hashes stored by earlier versions
+        let file = File::open("examples/id.export").unwrap();
+        let env = parse_lines(file, &ParseOptions::default(), &mut ()).unwrap();
+        let id = env.find_name("id").unwrap();
+        let h = DeclHashes::new(&env).hash(id);
+        assert_eq!(h.to_string().parse::<DeclHash>(), Ok(h));
+        assert_eq!(h.to_string(), "29879863735f43afa4fb2b6b95ad69c3");
+    }
+}
@@ -0,0 +1,59 @@
+//! Reading and checking Lean export files.
+//!
+//! An export file is parsed into an [`Environment`] with [`parse_lines`]; the
+//! declarations of the environment are then checked with
+//! [`check_declarations`] or [`check_declarations_parallel`]. The checks are
+//! structural, such as that constants are declared before they are used;
+//! terms are not type checked. Names, levels,
+//! expressions and declarations are referred to by their index in the export
+//! and looked up in the environment, which only hands out shared references
+//! to them.
+
+mod axioms;
+mod builder;
+mod cache;
+mod checker;
+mod copy;
+mod deps;
+mod dialect;
+mod diff;
+mod environment;
+mod hash;
+mod merge;
+mod minimize;
+mod observer;
+mod parallel;
+mod parser;
+mod reduce;
+mod report;
+mod slice;
+mod writer;
+
+pub use axioms::{AllowedAxioms, AxiomDeps};
+pub use builder::Builder;
+pub use cache::VerificationCache;
+pub use checker::{
+    check_declaration, check_declaration_timed, check_declarations, CheckError, CheckResult,
+    DeclResult,
+};
+pub use deps::{
+    dependency_chain, dependency_closure, direct_dependencies, transitive_dependencies,
+};
+pub use dialect::Dialect;
+pub use diff::{diff, DeclChange};
+pub use environment::{
+    Constant, Decl, EnvError, EnvResult, Environment, Expr, ExprId, InfoAnnotation, Level, LevelId,
+    NameId, NameItem, NotationKind,
+};
+pub use hash::{DeclHash, DeclHashes};
+pub use merge::Merger;
+pub use minimize::minimize;
+pub use observer::{Dump, Observer};
+pub use parallel::check_declarations_parallel;
+pub use parser::{
+    parse_lines, parse_lines_keep_going, parse_lines_pipelined, ParseError, ParseOptions,
+};
+pub use reduce::{ReduceError, ReduceOptions, ReduceResult, Reducer};
+pub use report::json_report;
+pub use slice::{slice, slice_with_axioms};
+pub use writer::write_export;
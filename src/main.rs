@@ -1,28 +1,708 @@
-mod environment;
-mod parser;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
 
-use parser::parse_lines;
+use lean_checker::{
+    check_declarations, check_declarations_parallel, dependency_closure, diff, direct_dependencies,
+    json_report, minimize, parse_lines, parse_lines_keep_going, parse_lines_pipelined, slice,
+    transitive_dependencies, write_export, AllowedAxioms, AxiomDeps, Decl, DeclChange, DeclHashes,
+    DeclResult, Dump, Environment, ExprId, Merger, NameId, Observer, ParseError, ParseOptions,
+    ReduceOptions, Reducer, VerificationCache,
+};
 
-fn process_file<R: std::io::Read>(file: R) -> Result<(), String> {
-    match parse_lines(file) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
+const USAGE: &str = "\
+Usage: lean-checker <command> [options] <export file> [arguments]
+
+Commands:
+  check [<file>...]         Check the structure of all declarations (reads
+                            stdin if no file or -); several exports are
+                            merged into one, where declarations in more than
+                            one must be identical. Terms are not type
+                            checked: constants must be declared earlier with
+                            the right number of universe levels, universe
+                            parameters declared, terms closed, constructors
+                            must build their type and projections name a
+                            field
+  print <file> [decl...]    Print the given declarations, or all of them
+  stats <file>              Print the number of items of each kind
+  deps <file> <decl>        Print the declarations <decl> depends on
+  axioms <file> [decl...]   Print the axioms the given declarations, or all of
+                            them, depend on, as <decl>: <axiom>, ...
+  hash <file> [decl...]     Print the content hashes of the given
+                            declarations, or all of them, which cover all
+                            they depend on
+  reduce <file> <decl>      Print the normal form of the value of a definition
+  diff <old> <new>          Print the declarations removed (-), added (+) and
+                            changed (~) from <old> to <new>, comparing terms
+                            up to binder and universe parameter names
+  slice <file> --target <decl> [-o <out>]
+                            Write an export of only <decl> and the
+                            declarations it depends on, renumbered
+  minimize <file> <decl> [-o <out>]
+                            Write a small export in which <decl> fails to
+                            check with the same error, or depends on the
+                            same disallowed axiom
+  help                      Print this message
+
+Options for all commands:
+  --dialect lean3|lean4     Export format dialect (default lean4), which only
+                            selects the commands accepted and the names of
+                            the quot constants and of sorry
+
+Options for check:
+  --keep-going              Report all parse errors instead of only the first
+  --dump names,levels,exprs,decls
+                            Print the items of the given categories
+  --jobs <n>                Check on <n> threads (default 1)
+  --report json             Print a JSON report of all declarations (not
+                            with --dump, which also prints to stdout)
+  --allow-axioms <a,b,...>  Fail if a declaration depends on any other axiom
+                            (by default only sorryAx, or sorry_ax in Lean 3,
+                            is disallowed)
+  --target <decl>           Only check <decl> and the declarations it depends
+                            on, and only audit the axioms of <decl>
+                            (repeatable)
+  --cache <file>            Record the hashes of declarations that check in
+                            <file>, and skip those recorded by earlier runs,
+                            unless they or their dependencies changed
+
+Options for deps:
+  --direct                  Only print direct dependencies
+
+Options for slice:
+  --target <decl>           Declaration to keep (repeatable, at least one)
+
+Options for slice and minimize:
+  -o <file>                 Write to <file> instead of stdout
+
+Options for minimize:
+  --allow-axioms <a,b,...>  Axioms allowed, as for check
+
+Options for reduce:
+  --whnf                    Only reduce to weak head normal form
+  --no-delta                Do not unfold definitions
+  --max-steps <n>           Give up after <n> reduction steps (default 1000000)
+
+Exit codes:
+  0  success
+  1  a declaration failed the structural checks or depends on a disallowed
+     axiom (check), reduction did not finish (reduce), or the exports differ
+     (diff)
+  2  usage error
+  3  parse error
+  4  I/O error
+";
+
+enum CliError {
+    Check(String),
+    Usage(String),
+    Parse(String),
+    Io(String),
+}
+
+impl CliError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            CliError::Check(_) => ExitCode::from(1),
+            CliError::Usage(_) => ExitCode::from(2),
+            CliError::Parse(_) => ExitCode::from(3),
+            CliError::Io(_) => ExitCode::from(4),
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            CliError::Check(msg)
+            | CliError::Usage(msg)
+            | CliError::Parse(msg)
+            | CliError::Io(msg) => msg,
+        }
+    }
+}
+
+type CliResult = Result<(), CliError>;
+
+/*
+ * Print lines to stdout as they are produced. Once the reader has gone
+ * away, as in `lean-checker axioms f | head`, the rest is dropped quietly
+ * and the command ends as it would have otherwise.
+ */
+fn print_lines(lines: impl IntoIterator<Item = String>) -> CliResult {
+    let mut out = std::io::stdout().lock();
+    for line in lines {
+        match writeln!(out, "{}", line) {
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => break,
+            Err(e) => return Err(CliError::Io(format!("stdout: {}", e))),
+            Ok(()) => {}
+        }
+    }
+    Ok(())
+}
+
+/*
+ * Command line arguments of a command: options are taken out as they are
+ * recognized, the remaining arguments are positional.
+ */
+struct Args {
+    parse: ParseOptions,
+    dump: Dump,
+    flags: Vec<String>,
+    // options with a value, in the order given
+    options: Vec<(String, String)>,
+    positional: Vec<String>,
+}
+
+impl Args {
+    // `flags` are the options without a value, `valued` those with one
+    fn parse(args: Vec<String>, flags: &[&str], valued: &[&str]) -> Result<Self, CliError> {
+        let mut result = Args {
+            parse: ParseOptions::default(),
+            dump: Dump::default(),
+            flags: vec![],
+            options: vec![],
+            positional: vec![],
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--dialect" || valued.contains(&arg.as_str()) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| CliError::Usage(format!("Expecting value after {}", arg)))?;
+                match arg.as_str() {
+                    "--dialect" => result.parse.dialect = value.parse().map_err(CliError::Usage)?,
+                    "--dump" => result.dump.enable(&value).map_err(CliError::Usage)?,
+                    _ => result.options.push((arg, value)),
+                }
+            } else if flags.contains(&arg.as_str()) {
+                result.flags.push(arg);
+            } else if arg.starts_with("--") {
+                return Err(CliError::Usage(format!("Unknown option {}", arg)));
+            } else {
+                result.positional.push(arg);
+            }
+        }
+        Ok(result)
+    }
+
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+
+    fn value<'b>(&'b self, option: &'b str) -> Option<&'b str> {
+        self.values(option).next()
+    }
+
+    // All values of an option that may be repeated
+    fn values<'b>(&'b self, option: &'b str) -> impl Iterator<Item = &'b str> {
+        self.options
+            .iter()
+            .filter(move |(o, _)| o == option)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn allowed_axioms(&self) -> AllowedAxioms {
+        match self.value("--allow-axioms") {
+            Some(list) => AllowedAxioms::Only(
+                list.split(',')
+                    .filter(|a| !a.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+            None => AllowedAxioms::AllButSorry,
+        }
+    }
+
+    // The export file followed by exactly `count` further arguments
+    fn expect_positional(&self, count: usize) -> Result<(&str, &[String]), CliError> {
+        match self.positional.split_first() {
+            Some((path, rest)) if rest.len() == count => Ok((path, rest)),
+            _ => Err(CliError::Usage(USAGE.to_string())),
+        }
+    }
+}
+
+fn open(path: &str) -> Result<Box<dyn std::io::Read + Send>, CliError> {
+    if path == "-" {
+        return Ok(Box::new(std::io::stdin()));
+    }
+    match std::fs::File::open(path) {
+        Ok(file) => Ok(Box::new(file)),
+        Err(e) => Err(CliError::Io(format!("{}: {}", path, e))),
+    }
+}
+
+fn load(path: &str, options: &ParseOptions) -> Result<Environment, CliError> {
+    load_observed(path, options, &mut ())
+}
+
+fn load_observed(
+    path: &str,
+    options: &ParseOptions,
+    observer: &mut dyn Observer,
+) -> Result<Environment, CliError> {
+    parse_lines(open(path)?, options, observer).map_err(|e| CliError::Parse(e.to_string()))
+}
+
+// Write an export to a file, or to stdout for -
+fn write(env: &Environment, path: &str) -> CliResult {
+    let io_error = |e: std::io::Error| CliError::Io(format!("{}: {}", path, e));
+    let writer: Box<dyn Write> = if path == "-" {
+        Box::new(std::io::stdout().lock())
+    } else {
+        Box::new(std::fs::File::create(path).map_err(io_error)?)
+    };
+    let mut writer = std::io::BufWriter::new(writer);
+    // As with print_lines, a closed stdout ends the output quietly
+    match write_export(env, &mut writer).and_then(|()| writer.flush()) {
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => result.map_err(io_error),
+    }
+}
+
+fn decl_of(env: &Environment, name: &str, nidx: Option<NameId>) -> Result<NameId, CliError> {
+    nidx.filter(|nidx| env.decl(*nidx).is_some())
+        .ok_or_else(|| CliError::Usage(format!("Unknown declaration {}", name)))
+}
+
+fn find_decl(env: &Environment, name: &str) -> Result<NameId, CliError> {
+    decl_of(env, name, env.find_name(name))
+}
+
+// Several declarations, looked up in one pass over the names
+fn find_each_decl<'a>(
+    env: &Environment,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<NameId>, CliError> {
+    let names = names.into_iter().collect::<Vec<_>>();
+    if names.is_empty() {
+        return Ok(vec![]);
+    }
+    let by_string = env.names_by_string();
+    names
+        .into_iter()
+        .map(|name| decl_of(env, name, by_string.get(name).copied()))
+        .collect()
+}
+
+// The named declarations, or all of them if none are named
+fn find_decls(env: &Environment, names: &[String]) -> Result<Vec<NameId>, CliError> {
+    if names.is_empty() {
+        return Ok(env.declarations().to_vec());
+    }
+    find_each_decl(env, names.iter().map(String::as_str))
+}
+
+// Several exports merged into one environment, with the parse errors of all
+fn load_merged(
+    paths: &[&str],
+    args: &Args,
+    dump: &mut Dump,
+) -> Result<(Environment, Vec<ParseError>), CliError> {
+    let mut merger = Merger::new(args.parse.dialect);
+    let mut parse_errors = vec![];
+    for path in paths {
+        let env = if args.has_flag("--keep-going") {
+            let (env, errors) = parse_lines_keep_going(open(path)?, &args.parse, dump);
+            for e in &errors {
+                eprintln!("{}: {}", path, e);
+            }
+            parse_errors.extend(errors);
+            env
+        } else {
+            load_observed(path, &args.parse, dump).map_err(|e| match e {
+                CliError::Parse(msg) => CliError::Parse(format!("{}: {}", path, msg)),
+                e => e,
+            })?
+        };
+        merger
+            .add(&env)
+            .map_err(|e| CliError::Check(format!("{}: {}", path, e)))?;
     }
+    Ok((merger.finish(), parse_errors))
 }
 
-fn main() -> Result<(), String> {
-    let args: Vec<String> = std::env::args().collect();
+fn cmd_check(args: Vec<String>) -> CliResult {
+    let args = Args::parse(
+        args,
+        &["--keep-going"],
+        &[
+            "--dump",
+            "--jobs",
+            "--report",
+            "--allow-axioms",
+            "--target",
+            "--cache",
+        ],
+    )?;
+    let paths = match args.positional.as_slice() {
+        [] => vec!["-"],
+        paths => paths.iter().map(String::as_str).collect(),
+    };
+    let report = match args.value("--report") {
+        None => false,
+        Some("json") => true,
+        Some(format) => {
+            return Err(CliError::Usage(format!(
+                "Unknown report format {} (expected json)",
+                format
+            )))
+        }
+    };
+
+    // Both are printed on stdout
+    if report && args.dump != Dump::default() {
+        return Err(CliError::Usage(
+            "--report json cannot be combined with --dump".to_string(),
+        ));
+    }
 
-    if args.len() == 1 {
-        let handle = std::io::stdin().lock();
-        process_file(handle)
-    } else if args.len() == 2 {
-        match std::fs::File::open(&args[1]) {
-            Ok(file) => process_file(file),
-            Err(e) => Err(e.to_string())
+    let jobs = match args.value("--jobs") {
+        Some(jobs) => match jobs.parse::<usize>() {
+            Ok(jobs) if jobs > 0 => jobs,
+            _ => return Err(CliError::Usage(format!("Invalid job count {}", jobs))),
+        },
+        None => 1,
+    };
+
+    // Without options that need the whole environment first, declarations
+    // are checked while the rest of the input is being parsed
+    let pipelined = paths.len() == 1
+        && !args.has_flag("--keep-going")
+        && args.value("--cache").is_none()
+        && jobs == 1
+        && args.values("--target").next().is_none();
+    let mut dump = args.dump;
+    // The results of pipelined checking come with the environment
+    let (env, parse_errors, checked) = if paths.len() > 1 {
+        let (env, parse_errors) = load_merged(&paths, &args, &mut dump)?;
+        (env, parse_errors, None)
+    } else if args.has_flag("--keep-going") {
+        let (env, parse_errors) = parse_lines_keep_going(open(paths[0])?, &args.parse, &mut dump);
+        for e in &parse_errors {
+            eprintln!("{}", e);
+        }
+        (env, parse_errors, None)
+    } else if pipelined {
+        let (env, results) = parse_lines_pipelined(open(paths[0])?, &args.parse, &mut dump)
+            .map_err(|e| CliError::Parse(e.to_string()))?;
+        (env, vec![], Some(results))
+    } else {
+        let env = load_observed(paths[0], &args.parse, &mut dump)?;
+        (env, vec![], None)
+    };
+
+    let allowed = args.allowed_axioms();
+    let targets = find_each_decl(&env, args.values("--target"))?;
+
+    let mut results = if let Some(results) = checked {
+        results
+    } else {
+        let mut decls = if targets.is_empty() {
+            env.declarations().to_vec()
+        } else {
+            dependency_closure(&env, &targets)
+        };
+        // Declarations in the cache are taken as checked
+        let mut hashes = DeclHashes::new(&env);
+        let mut cache = match args.value("--cache") {
+            Some(path) => Some(
+                VerificationCache::load(Path::new(path), &env)
+                    .map_err(|e| CliError::Io(format!("{}: {}", path, e)))?,
+            ),
+            None => None,
+        };
+        let mut cached = vec![];
+        if let Some(cache) = &cache {
+            (cached, decls) = decls
+                .into_iter()
+                .partition(|n| cache.is_verified(&env, &mut hashes, *n));
+        }
+        let mut results = if jobs > 1 {
+            check_declarations_parallel(&env, &decls, jobs, &mut ())
+        } else {
+            check_declarations(&env, &decls, &mut ())
+        };
+        if let (Some(cache), Some(path)) = (&mut cache, args.value("--cache")) {
+            for r in results.iter().filter(|r| r.result.is_ok()) {
+                cache.insert(hashes.hash(r.nidx));
+            }
+            cache
+                .save(Path::new(path))
+                .map_err(|e| CliError::Io(format!("{}: {}", path, e)))?;
         }
+        results.extend(cached.into_iter().map(|nidx| DeclResult {
+            nidx,
+            result: Ok(()),
+            duration: Duration::ZERO,
+            cached: true,
+        }));
+        results.sort_by_key(|r| env.decl_position(r.nidx));
+        results
+    };
+    let mut axiom_deps = AxiomDeps::new(&env);
+    for r in &mut results {
+        if r.result.is_ok() && (targets.is_empty() || targets.contains(&r.nidx)) {
+            r.result = axiom_deps.check_allowed(r.nidx, &allowed);
+        }
+    }
+    let mut failed = 0;
+    for r in &results {
+        if let Err(e) = &r.result {
+            eprintln!("Error in {}: {}", env.name_to_string(r.nidx), e);
+            failed += 1;
+        }
+    }
+    if report {
+        print_lines([json_report(&env, &results, &parse_errors)])?;
+    }
+
+    if !parse_errors.is_empty() {
+        let dependent = parse_errors.iter().filter(|e| e.is_dependent()).count();
+        Err(CliError::Parse(format!(
+            "{} lines failed ({} errors, {} skipped as dependent)",
+            parse_errors.len(),
+            parse_errors.len() - dependent,
+            dependent
+        )))
+    } else if failed > 0 {
+        Err(CliError::Check(format!(
+            "{} of {} declarations failed to check",
+            failed,
+            results.len()
+        )))
     } else {
-        println!("Usage: lean-checker <export file path>");
         Ok(())
     }
 }
+
+fn cmd_print(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &[], &[])?;
+    let (path, names) = match args.positional.split_first() {
+        Some(split) => split,
+        None => return Err(CliError::Usage(USAGE.to_string())),
+    };
+    let env = load(path, &args.parse)?;
+    let nidxs = find_decls(&env, names)?;
+    print_lines(nidxs.into_iter().map(|n| env.decl_to_string(n)))
+}
+
+fn cmd_stats(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &[], &[])?;
+    let (path, _) = args.expect_positional(0)?;
+    let env = load(path, &args.parse)?;
+    let decls = env.declarations();
+    let count = |kind: &str| {
+        decls
+            .iter()
+            .filter(|n| env.decl(**n).map(|d| d.kind()) == Some(kind))
+            .count()
+    };
+    let constructors: usize = decls
+        .iter()
+        .map(|n| match env.decl(*n) {
+            Some(Decl::Ind(_, _, intros, _)) => intros.len(),
+            _ => 0,
+        })
+        .sum();
+    print_lines([
+        format!("names: {}", env.name_count()),
+        format!("levels: {}", env.level_count()),
+        format!("expressions: {}", env.expr_count()),
+        format!("declarations: {}", decls.len()),
+        format!("  axioms: {}", count("axiom")),
+        format!("  definitions: {}", count("definition")),
+        format!("  inductives: {}", count("inductive")),
+        format!("  constructors: {}", constructors),
+        format!("notations: {}", env.notation_count()),
+        format!("quot: {}", if env.has_quot() { "yes" } else { "no" }),
+    ])
+}
+
+fn cmd_deps(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &["--direct"], &[])?;
+    let (path, rest) = args.expect_positional(1)?;
+    let env = load(path, &args.parse)?;
+    let nidx = find_decl(&env, &rest[0])?;
+    let deps = if args.has_flag("--direct") {
+        direct_dependencies(&env, nidx)
+    } else {
+        transitive_dependencies(&env, nidx)
+    };
+    print_lines(deps.into_iter().map(|d| env.name_to_string(d)))
+}
+
+fn cmd_axioms(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &[], &[])?;
+    let (path, names) = match args.positional.split_first() {
+        Some(split) => split,
+        None => return Err(CliError::Usage(USAGE.to_string())),
+    };
+    let env = load(path, &args.parse)?;
+    let mut axiom_deps = AxiomDeps::new(&env);
+    let nidxs = find_decls(&env, names)?;
+    print_lines(nidxs.into_iter().map(|nidx| {
+        let axioms = axiom_deps
+            .axioms(nidx)
+            .iter()
+            .map(|a| env.name_to_string(*a))
+            .collect::<Vec<_>>();
+        let name = env.name_to_string(nidx);
+        if axioms.is_empty() {
+            format!("{}:", name)
+        } else {
+            format!("{}: {}", name, axioms.join(", "))
+        }
+    }))
+}
+
+fn cmd_hash(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &[], &[])?;
+    let (path, names) = match args.positional.split_first() {
+        Some(split) => split,
+        None => return Err(CliError::Usage(USAGE.to_string())),
+    };
+    let env = load(path, &args.parse)?;
+    let mut hashes = DeclHashes::new(&env);
+    let nidxs = find_decls(&env, names)?;
+    print_lines(
+        nidxs
+            .into_iter()
+            .map(|n| format!("{} {}", hashes.hash(n), env.name_to_string(n))),
+    )
+}
+
+fn cmd_reduce(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &["--whnf", "--no-delta"], &["--max-steps"])?;
+    let (path, rest) = args.expect_positional(1)?;
+    let mut options = ReduceOptions {
+        delta: !args.has_flag("--no-delta"),
+        ..Default::default()
+    };
+    if let Some(max_steps) = args.value("--max-steps") {
+        options.max_steps = max_steps
+            .parse()
+            .map_err(|_| CliError::Usage(format!("Invalid step count {}", max_steps)))?;
+    }
+    let mut env = load(path, &args.parse)?;
+    let nidx = find_decl(&env, &rest[0])?;
+    let value = definition_value(&env, nidx)?;
+    let mut reducer = Reducer::new(&mut env, options);
+    let result = if args.has_flag("--whnf") {
+        reducer.whnf(value)
+    } else {
+        reducer.normalize(value)
+    };
+    match result {
+        Ok(eidx) => print_lines([env.expr_to_string(eidx)]),
+        Err(e) => Err(CliError::Check(e.to_string())),
+    }
+}
+
+fn definition_value(env: &Environment, nidx: NameId) -> Result<ExprId, CliError> {
+    match env.decl(nidx) {
+        Some(Decl::Def(_, value, _)) => Ok(*value),
+        _ => Err(CliError::Usage(format!(
+            "{} is not a definition",
+            env.name_to_string(nidx)
+        ))),
+    }
+}
+
+fn cmd_diff(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &[], &[])?;
+    let (old_path, rest) = args.expect_positional(1)?;
+    let old = load(old_path, &args.parse)?;
+    let new = load(&rest[0], &args.parse)?;
+    let changes = diff(&old, &new);
+    print_lines(changes.iter().map(|change| match change {
+        DeclChange::Removed(name) => format!("- {}", name),
+        DeclChange::Added(name) => format!("+ {}", name),
+        DeclChange::Changed {
+            name,
+            type_changed,
+            value_changed,
+        } => {
+            let what = match (type_changed, value_changed) {
+                (true, true) => "type, value",
+                (true, false) => "type",
+                _ => "value",
+            };
+            format!("~ {} ({})", name, what)
+        }
+    }))?;
+    if changes.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::Check(format!(
+            "{} declarations differ",
+            changes.len()
+        )))
+    }
+}
+
+fn cmd_slice(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &[], &["--target", "-o"])?;
+    let (path, _) = args.expect_positional(0)?;
+    let env = load(path, &args.parse)?;
+    let targets = find_each_decl(&env, args.values("--target"))?;
+    if targets.is_empty() {
+        return Err(CliError::Usage(
+            "Expecting at least one --target".to_string(),
+        ));
+    }
+    write(&slice(&env, &targets), args.value("-o").unwrap_or("-"))
+}
+
+fn cmd_minimize(args: Vec<String>) -> CliResult {
+    let args = Args::parse(args, &[], &["-o", "--allow-axioms"])?;
+    let (path, rest) = args.expect_positional(1)?;
+    let env = load(path, &args.parse)?;
+    let nidx = find_decl(&env, &rest[0])?;
+    let Some(min) = minimize(&env, nidx, &args.allowed_axioms()) else {
+        return Err(CliError::Usage(format!(
+            "{} checks, nothing to minimize",
+            rest[0]
+        )));
+    };
+    eprintln!(
+        "Kept {} of {} declarations",
+        min.declarations().len(),
+        env.declarations().len()
+    );
+    write(&min, args.value("-o").unwrap_or("-"))
+}
+
+fn run(args: Vec<String>) -> CliResult {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest.to_vec()),
+        None => ("check", vec![]),
+    };
+    match command {
+        "check" => cmd_check(rest),
+        "print" => cmd_print(rest),
+        "stats" => cmd_stats(rest),
+        "deps" => cmd_deps(rest),
+        "axioms" => cmd_axioms(rest),
+        "hash" => cmd_hash(rest),
+        "reduce" => cmd_reduce(rest),
+        "diff" => cmd_diff(rest),
+        "slice" => cmd_slice(rest),
+        "minimize" => cmd_minimize(rest),
+        "help" | "--help" | "-h" => print_lines([USAGE.trim_end().to_string()]),
+        _ => Err(CliError::Usage(format!(
+            "Unknown command {}\n\n{}",
+            command, USAGE
+        ))),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e.message().trim_end());
+            e.exit_code()
+        }
+    }
+}
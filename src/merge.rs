@@ -0,0 +1,114 @@
+use super::copy::{Copier, Destination};
+use super::dialect::Dialect;
+use super::environment::{EnvResult, Environment};
+
+/*
+ * Several environments, such as the exports of the modules of a build,
+ * merged into one. Each is added in turn with its own indices mapped to
+ * those of the merged environment, and its declarations follow those of the
+ * environments added before. A declaration may be in several environments
+ * if it is identical in all of them.
+ */
+pub struct Merger {
+    dest: Destination,
+}
+
+impl Merger {
+    pub fn new(dialect: Dialect) -> Self {
+        Self {
+            dest: Destination::new(Environment::with_dialect(dialect)),
+        }
+    }
+
+    pub fn add(&mut self, env: &Environment) -> EnvResult<()> {
+        let mut copier = Copier::new(env, &mut self.dest, &[]);
+        if env.has_quot() {
+            copier.quot();
+        }
+        for nidx in env.declarations() {
+            copier.decl(*nidx)?;
+        }
+        let notations = env
+            .notations()
+            .map(|(kind, nidx, prec, token)| (*kind, copier.name(nidx), prec, token))
+            .collect::<Vec<_>>();
+        for (kind, nidx, prec, token) in notations {
+            let merged = &mut self.dest.env;
+            if !merged.notations().any(|n| n == (&kind, nidx, prec, token)) {
+                merged.add_notation(kind, nidx, prec, token.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Environment {
+        self.dest.env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check_declarations, parse_lines, EnvError, ParseOptions};
+
+    fn parse(export: &str) -> Environment {
+        parse_lines(export.as_bytes(), &ParseOptions::default(), &mut ()).unwrap()
+    }
+
+    #[test]
+    fn test_merge() {
+        /*
+         * axiom p : Prop
+         * def q : Prop := p
+         */
+        let a = parse(
+            "\
+1 #NS 0 p
+2 #NS 0 q
+0 #ES 0
+1 #EC 1
+#AX 1 0
+#DEF 2 0 1
+",
+        );
+        /*
+         * axiom p : Prop
+         * def r : Prop := q
+         * with other indices
+         */
+        let b = parse(
+            "\
+1 #NS 0 r
+2 #NS 0 q
+3 #NS 0 p
+0 #EC 2
+1 #ES 0
+#AX 3 1
+#DEF 1 1 0
+",
+        );
+        let mut merger = Merger::new(Dialect::Lean4);
+        merger.add(&a).unwrap();
+        merger.add(&b).unwrap();
+        let env = merger.finish();
+        let names = env.declarations().iter().map(|n| env.name_to_string(*n));
+        assert_eq!(names.collect::<Vec<_>>(), ["p", "q", "r"]);
+        let results = check_declarations(&env, env.declarations(), &mut ());
+        assert!(results.iter().all(|r| r.result.is_ok()));
+
+        // def q : Prop := Prop
+        let c = parse(
+            "\
+1 #NS 0 q
+0 #ES 0
+#DEF 1 0 0
+",
+        );
+        let mut merger = Merger::new(Dialect::Lean4);
+        merger.add(&a).unwrap();
+        assert_eq!(
+            merger.add(&c),
+            Err(EnvError::ConflictingDecl("q".to_string()))
+        );
+    }
+}
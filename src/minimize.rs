@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+
+use super::axioms::{AllowedAxioms, AxiomDeps};
+use super::checker::{check_declaration, CheckError, CheckResult};
+use super::deps::dependency_closure;
+use super::environment::{Decl, Environment, NameId};
+use super::slice::slice_with_axioms;
+
+// The check error of a declaration, or else the axiom audit as in check
+fn check(env: &Environment, nidx: NameId, allowed: &AllowedAxioms) -> CheckResult {
+    check_declaration(env, nidx)?;
+    AxiomDeps::new(env).check_allowed(nidx, allowed)
+}
+
+// A disallowed axiom is the same failure through any chain of declarations
+fn same_failure(a: &CheckError, b: &CheckError) -> bool {
+    match (a, b) {
+        (CheckError::DisallowedAxiom(c1), CheckError::DisallowedAxiom(c2)) => {
+            c1.last() == c2.last()
+        }
+        _ => a == b,
+    }
+}
+
+/*
+ * A small environment in which a declaration fails to check with the same
+ * error as in `env`, or depends on the same disallowed axiom, or None if it
+ * passes both. Declarations it does not depend on are dropped, then delta
+ * debugging looks for a large set of the definitions it depends on that can
+ * be replaced by axioms, which drops whatever only their values depend on.
+ * Items are renumbered as by slice.
+ */
+pub fn minimize(env: &Environment, target: NameId, allowed: &AllowedAxioms) -> Option<Environment> {
+    let error = check(env, target, allowed).err()?;
+    let target_name = env.name_to_string(target);
+    // The slice with the given definitions as axioms, if it still fails
+    let reproduce = |axioms: &[NameId]| {
+        let sliced = slice_with_axioms(env, &[target], axioms);
+        let t = *sliced
+            .declarations()
+            .iter()
+            .find(|n| sliced.name_to_string(**n) == target_name)
+            .expect("Target not in slice");
+        let failure = check(&sliced, t, allowed).err();
+        failure
+            .is_some_and(|e| same_failure(&e, &error))
+            .then_some(sliced)
+    };
+    let mut smallest = reproduce(&[])?;
+
+    let mut candidates = dependency_closure(env, &[target]);
+    candidates.retain(|n| *n != target && matches!(env.decl(*n), Some(Decl::Def(..))));
+    // Later definitions first, as they tend to hide more
+    candidates.reverse();
+    let mut axioms: Vec<NameId> = vec![];
+    let mut chunks = 2;
+    while !candidates.is_empty() {
+        let size = candidates.len().div_ceil(chunks);
+        let found = candidates.chunks(size).enumerate().find_map(|(i, chunk)| {
+            let sliced = reproduce(&[&axioms[..], chunk].concat())?;
+            Some((i, sliced))
+        });
+        match found {
+            Some((i, sliced)) => {
+                let end = ((i + 1) * size).min(candidates.len());
+                axioms.extend(candidates.drain(i * size..end));
+                // Definitions left out of the slice need not be tried
+                let kept = sliced
+                    .declarations()
+                    .iter()
+                    .map(|n| sliced.name_to_string(*n))
+                    .collect::<HashSet<_>>();
+                candidates.retain(|n| kept.contains(&env.name_to_string(*n)));
+                smallest = sliced;
+                chunks = (chunks - 1).max(2);
+            }
+            None if size == 1 => break,
+            None => chunks = (chunks * 2).min(candidates.len()),
+        }
+    }
+    Some(smallest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{term, Builder};
+
+    #[test]
+    fn test_minimize() {
+        /*
+         * axiom p : Prop
+         * def q : Prop := p
+         * def r : Prop := q
+         * def s : Prop := p
+         * def bad : Prop := r.{1}
+         */
+        let mut b = Builder::new();
+        let prop = b.prop();
+        b.axiom("p", prop, &[]);
+        let p = b.cnst("p", &[]);
+        b.def("q", prop, p, &[]);
+        let q = b.cnst("q", &[]);
+        b.def("r", prop, q, &[]);
+        b.def("s", prop, p, &[]);
+        let r = term!(b, (const "r" 1));
+        let bad = b.def("bad", prop, r, &[]);
+        let env = b.build();
+
+        let allowed = AllowedAxioms::AllButSorry;
+        let min = minimize(&env, bad, &allowed).unwrap();
+        let decls = min.declarations();
+        let names = decls.iter().map(|n| min.name_to_string(*n));
+        assert_eq!(names.collect::<Vec<_>>(), ["r", "bad"]);
+        assert!(matches!(min.decl(decls[0]), Some(Decl::Axiom(..))));
+        assert_eq!(
+            check_declaration(&min, decls[1]),
+            Err(CheckError::LevelCount("r".to_string(), 0, 1))
+        );
+
+        let q = env.find_name("q").unwrap();
+        assert!(minimize(&env, q, &allowed).is_none());
+    }
+
+    #[test]
+    fn test_minimize_axioms() {
+        /*
+         * axiom p : Prop
+         * def q : Prop := p
+         * def r : Prop := q
+         * def s : Prop := r
+         * with only r allowed as an axiom
+         */
+        let mut b = Builder::new();
+        let prop = b.prop();
+        b.axiom("p", prop, &[]);
+        let p = b.cnst("p", &[]);
+        b.def("q", prop, p, &[]);
+        let q = b.cnst("q", &[]);
+        b.def("r", prop, q, &[]);
+        let r = b.cnst("r", &[]);
+        let s = b.def("s", prop, r, &[]);
+        let env = b.build();
+
+        // Turning r or q into an axiom would hide p
+        let allowed = AllowedAxioms::Only(HashSet::from(["r".to_string()]));
+        let min = minimize(&env, s, &allowed).unwrap();
+        assert_eq!(min.declarations().len(), 4);
+        let t = min.find_name("s").unwrap();
+        assert_eq!(
+            AxiomDeps::new(&min).check_allowed(t, &allowed),
+            Err(CheckError::DisallowedAxiom(
+                ["s", "r", "q", "p"].map(String::from).to_vec()
+            ))
+        );
+        assert!(minimize(&env, s, &AllowedAxioms::AllButSorry).is_none());
+    }
+}
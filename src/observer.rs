@@ -0,0 +1,117 @@
+use core::fmt;
+use std::io::{self, Write};
+
+use super::checker::DeclResult;
+use super::environment::{Environment, ExprId, LevelId, NameId};
+use super::parser::ParseError;
+
+/*
+ * Hooks called while an export is parsed and checked. Items are passed once
+ * they have been added to the environment, declarations once they have been
+ * checked. Every method does nothing by default, and () is the observer that
+ * ignores everything.
+ */
+pub trait Observer {
+    fn on_name(&mut self, _env: &Environment, _idx: NameId) {}
+    fn on_level(&mut self, _env: &Environment, _idx: LevelId) {}
+    fn on_expr(&mut self, _env: &Environment, _idx: ExprId) {}
+    fn on_decl(&mut self, _env: &Environment, _idx: NameId) {}
+    // Lean 3 notation, by position among the notations of the environment
+    fn on_notation(&mut self, _env: &Environment, _i: usize) {}
+    fn on_decl_checked(&mut self, _env: &Environment, _result: &DeclResult) {}
+    fn on_error(&mut self, _error: &ParseError) {}
+}
+
+impl Observer for () {}
+
+/*
+ * Observer printing categories of items as they are parsed. Nothing is
+ * printed by default.
+ */
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Dump {
+    pub names: bool,
+    pub levels: bool,
+    pub exprs: bool,
+    pub decls: bool,
+}
+
+impl Dump {
+    // Enable the categories in a comma-separated list such as "names,decls"
+    pub fn enable(&mut self, categories: &str) -> Result<(), String> {
+        for category in categories.split(',') {
+            match category {
+                "names" => self.names = true,
+                "levels" => self.levels = true,
+                "exprs" => self.exprs = true,
+                "decls" => self.decls = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown dump category '{}' (expected names, levels, exprs or decls)",
+                        category
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Dumping is best effort: once stdout is closed, as in `check --dump decls |
+// head`, the rest is dropped rather than stopping the check
+fn dump_line(line: fmt::Arguments) {
+    let _ = writeln!(io::stdout().lock(), "{}", line);
+}
+
+impl Observer for Dump {
+    fn on_name(&mut self, env: &Environment, idx: NameId) {
+        if self.names {
+            dump_line(format_args!("Name {}: {}", idx, env.name_to_string(idx)));
+        }
+    }
+
+    fn on_level(&mut self, env: &Environment, idx: LevelId) {
+        if self.levels {
+            dump_line(format_args!("Level {}: {}", idx, env.level_to_string(idx)));
+        }
+    }
+
+    fn on_expr(&mut self, env: &Environment, idx: ExprId) {
+        if self.exprs {
+            dump_line(format_args!("Expr {}: {}", idx, env.expr_to_string(idx)));
+        }
+    }
+
+    fn on_decl(&mut self, env: &Environment, idx: NameId) {
+        if self.decls {
+            dump_line(format_args!(
+                "Declaration {}: {}",
+                idx,
+                env.decl_to_string(idx)
+            ));
+        }
+    }
+
+    fn on_notation(&mut self, env: &Environment, i: usize) {
+        if self.decls {
+            dump_line(format_args!(
+                "Notation {}: {}",
+                i,
+                env.notation_to_string(i)
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump() {
+        let mut dump = Dump::default();
+        dump.enable("names,decls").unwrap();
+        assert!(dump.names && dump.decls && !dump.levels && !dump.exprs);
+        assert!(dump.enable("types").is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use super::checker::{check_declaration_timed, DeclResult};
+use super::environment::{Environment, NameId};
+use super::observer::Observer;
+
+/*
+ * Checks declarations on a pool of threads sharing the environment. The
+ * check of a declaration does not depend on the results of the others, so
+ * the workers take the declarations in order without waiting for each
+ * other. The results are returned in the order of the given declarations,
+ * independently of the scheduling; the observer sees them in the order
+ * they are found.
+ */
+
+struct Results<'o> {
+    results: Vec<Option<DeclResult>>,
+    observer: &'o mut (dyn Observer + Send),
+}
+
+pub fn check_declarations_parallel(
+    env: &Environment,
+    nidxs: &[NameId],
+    jobs: usize,
+    observer: &mut (dyn Observer + Send),
+) -> Vec<DeclResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Results {
+        results: (0..nidxs.len()).map(|_| None).collect(),
+        observer,
+    });
+
+    let worker = || loop {
+        let i = next.fetch_add(1, Ordering::Relaxed);
+        let Some(nidx) = nidxs.get(i) else {
+            return;
+        };
+        let result = check_declaration_timed(env, *nidx);
+        let mut r = results.lock().unwrap();
+        r.observer.on_decl_checked(env, &result);
+        r.results[i] = Some(result);
+    };
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(worker);
+        }
+    });
+
+    let results = results.into_inner().unwrap().results;
+    results
+        .into_iter()
+        .map(|r| r.expect("Declaration not checked"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checker::check_declarations;
+    use crate::parser::{parse_lines, ParseOptions};
+
+    #[test]
+    fn test_parallel() {
+        /*
+         * axiom a : Prop
+         * def b : Prop := c   -- refers to a later declaration
+         * def c : Prop := a
+         * def d.{u} : Prop := c.{u}
+         */
+        let export = "\
+1 #NS 0 a
+2 #NS 0 b
+3 #NS 0 c
+4 #NS 0 d
+5 #NS 0 u
+1 #UP 5
+0 #ES 0
+1 #EC 1
+2 #EC 3
+3 #EC 3 1
+#AX 1 0
+#DEF 2 0 2
+#DEF 3 0 1
+#DEF 4 0 3 5
+";
+        let env = parse_lines(export.as_bytes(), &ParseOptions::default(), &mut ()).unwrap();
+        let summary = |results: Vec<DeclResult>| {
+            results
+                .into_iter()
+                .map(|r| (r.nidx, r.result))
+                .collect::<Vec<_>>()
+        };
+        let expected = summary(check_declarations(&env, env.declarations(), &mut ()));
+        assert!(expected[1].1.is_err() && expected[3].1.is_err());
+        for jobs in [1, 2, 8] {
+            let results = check_declarations_parallel(&env, env.declarations(), jobs, &mut ());
+            assert_eq!(summary(results), expected);
+        }
+    }
+}
@@ -1,33 +1,68 @@
+use std::collections::HashMap;
 use std::io::{prelude::*, BufReader, Read};
+use std::str::FromStr;
+use std::sync::mpsc::{channel, sync_channel};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+
+use super::checker::{check_declaration_timed, DeclResult};
+use super::dialect::Dialect;
+use super::environment::{
+    EnvError, Environment, ExprId, InfoAnnotation, LevelId, NameId, NameItem, NotationKind,
+};
+use super::observer::Observer;
+
+#[derive(Debug)]
+enum LineError {
+    Syntax(String),
+    Env(EnvError),
+    // Refers to an index whose defining line (given) failed
+    Dependent(usize),
+}
 
-use super::environment::{Environment, InfoAnnotation, NameItem};
-
-struct LineError {
-    msg: String,
+impl LineError {
+    // The index whose absence caused this error, if any
+    fn missing_index(&self) -> Option<IndexKind> {
+        match self {
+            LineError::Env(EnvError::UnknownName(idx)) => Some(IndexKind::Name(*idx)),
+            LineError::Env(EnvError::UnknownLevel(idx)) => Some(IndexKind::Level(*idx)),
+            LineError::Env(EnvError::UnknownExpr(idx)) => Some(IndexKind::Expr(*idx)),
+            _ => None,
+        }
+    }
 }
 
 impl From<&str> for LineError {
     fn from(value: &str) -> Self {
-        Self {
-            msg: String::from(value),
-        }
+        Self::Syntax(String::from(value))
     }
 }
 
 impl From<std::io::Error> for LineError {
     fn from(err: std::io::Error) -> Self {
-        Self {
-            msg: err.to_string(),
-        }
+        Self::Syntax(err.to_string())
+    }
+}
+
+impl From<EnvError> for LineError {
+    fn from(err: EnvError) -> Self {
+        Self::Env(err)
     }
 }
 
 impl std::fmt::Display for LineError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.msg.fmt(f)
+        match self {
+            LineError::Syntax(msg) => msg.fmt(f),
+            LineError::Env(err) => err.fmt(f),
+            LineError::Dependent(line_no) => {
+                write!(f, "Skipped, depends on line {} which failed", line_no)
+            }
+        }
     }
 }
 
+#[derive(Debug)]
 pub struct ParseError {
     line_error: LineError,
     line_no: usize,
@@ -40,6 +75,11 @@ impl ParseError {
             line_no,
         }
     }
+
+    // True if this error only follows from an earlier error
+    pub fn is_dependent(&self) -> bool {
+        matches!(self.line_error, LineError::Dependent(_))
+    }
 }
 
 impl std::fmt::Display for ParseError {
@@ -52,12 +92,40 @@ impl std::fmt::Display for ParseError {
     }
 }
 
-struct Parser {
+#[derive(Default)]
+pub struct ParseOptions {
+    pub dialect: Dialect,
+}
+
+struct Parser<'o> {
     env: Environment,
+    observer: &'o mut dyn Observer,
 }
 
 type LineResult<T> = std::result::Result<T, LineError>;
-type Index = usize;
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum IndexKind {
+    Name(NameId),
+    Level(LevelId),
+    Expr(ExprId),
+}
+
+// The index a line defines, determined without fully parsing it
+fn defined_index(line: &str) -> Option<IndexKind> {
+    let (first, rest) = next(line)?;
+    let idx = first.parse::<u32>().ok()?;
+    let (cmd, _) = next(rest)?;
+    if cmd.starts_with("#N") {
+        Some(IndexKind::Name(idx.into()))
+    } else if cmd.starts_with("#U") {
+        Some(IndexKind::Level(idx.into()))
+    } else if cmd.starts_with("#E") {
+        Some(IndexKind::Expr(idx.into()))
+    } else {
+        None
+    }
+}
 
 fn parse_info_annotation(s: &str) -> LineResult<InfoAnnotation> {
     match s {
@@ -76,9 +144,10 @@ fn next(s: &str) -> Option<(&str, &str)> {
     })
 }
 
-fn next_idx(s: &str) -> Option<(Index, &str)> {
+// An index or a number
+fn next_idx<T: FromStr>(s: &str) -> Option<(T, &str)> {
     match next(s) {
-        Some((t, r)) => t.parse::<usize>().map(|i| (i, r)).ok(),
+        Some((t, r)) => t.parse::<T>().map(|i| (i, r)).ok(),
         None => None,
     }
 }
@@ -90,245 +159,385 @@ fn check_eol(s: &str) -> LineResult<()> {
     }
 }
 
-impl Parser {
-    fn new() -> Self {
-        Self {
-            env: Environment::new(),
-        }
+fn parse_hex_string(s: &str) -> LineResult<String> {
+    let mut bytes: Vec<u8> = vec![];
+    let mut rest = s;
+    while let Some((t, r)) = next(rest) {
+        let b = u8::from_str_radix(t, 16).map_err(|_| "Expecting hex byte")?;
+        bytes.push(b);
+        rest = r;
     }
+    String::from_utf8(bytes).map_err(|_| LineError::from("Invalid UTF-8 string"))
+}
 
-    fn post_add_name(&self, idx: Index) {
-        println!("Name {}: {}", idx, self.env.name_to_string(idx));
+/*
+ * A parsed line. Parsing a line does not need the environment, so lines can
+ * be parsed on one thread and added to the environment on another.
+ */
+enum Command {
+    Name(NameId, NameItem, NameId),
+    LevelSucc(LevelId, LevelId),
+    LevelMax(LevelId, LevelId, LevelId),
+    LevelIMax(LevelId, LevelId, LevelId),
+    LevelParam(LevelId, NameId),
+    Sort(ExprId, LevelId),
+    BoundVar(ExprId, usize),
+    Pi(ExprId, InfoAnnotation, NameId, ExprId, ExprId),
+    Lambda(ExprId, InfoAnnotation, NameId, ExprId, ExprId),
+    Constant(ExprId, NameId, Vec<LevelId>),
+    FunAppl(ExprId, ExprId, ExprId),
+    Let(ExprId, NameId, ExprId, ExprId, ExprId),
+    Proj(ExprId, NameId, usize, ExprId),
+    NatLit(ExprId, String),
+    StrLit(ExprId, String),
+    Definition(NameId, ExprId, ExprId, Vec<NameId>),
+    Inductive(usize, NameId, ExprId, Vec<(NameId, ExprId)>, Vec<NameId>),
+    Axiom(NameId, ExprId, Vec<NameId>),
+    Quot,
+    Notation(NotationKind, NameId, usize, String),
+}
+
+// Indices up to the end of the line
+fn rest_idxs<T: FromStr>(s: &str) -> LineResult<Vec<T>> {
+    let mut idxs: Vec<T> = vec![];
+    let mut rest = s;
+    while let Some((i, r)) = next_idx(rest) {
+        idxs.push(i);
+        rest = r;
     }
+    check_eol(rest)?;
+    Ok(idxs)
+}
+
+fn parse_ni(idx: NameId, s: &str) -> LineResult<Command> {
+    let (p, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (i, rest) = next_idx(rest).ok_or("Expecting integer")?;
+    check_eol(rest)?;
+    Ok(Command::Name(idx, NameItem::Int(i), p))
+}
+
+fn parse_ns(idx: NameId, s: &str) -> LineResult<Command> {
+    let (p, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (s, rest) = next(rest).ok_or("Expecting identifier")?;
+    check_eol(rest)?;
+    Ok(Command::Name(idx, NameItem::Str(s.to_string()), p))
+}
+
+/*
+ * <uidx'> #US  <uidx>
+ * <uidx'> #UM  <uidx_1> <uidx_2>
+ * <uidx'> #UIM <uidx_1> <uidx_2>
+ * <uidx'> #UP  <nidx>
+ */
+
+fn parse_us(idx: LevelId, s: &str) -> LineResult<Command> {
+    let (u, rest) = next_idx(s).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::LevelSucc(idx, u))
+}
+
+fn parse_um(idx: LevelId, s: &str) -> LineResult<Command> {
+    let (u1, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (u2, rest) = next_idx(rest).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::LevelMax(idx, u1, u2))
+}
+
+fn parse_uim(idx: LevelId, s: &str) -> LineResult<Command> {
+    let (u1, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (u2, rest) = next_idx(rest).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::LevelIMax(idx, u1, u2))
+}
+
+fn parse_up(idx: LevelId, s: &str) -> LineResult<Command> {
+    let (n, rest) = next_idx(s).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::LevelParam(idx, n))
+}
+
+fn parse_es(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (u, rest) = next_idx(s).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::Sort(idx, u))
+}
+
+fn parse_ev(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (i, rest) = next_idx(s).ok_or("Expecting integer")?;
+    check_eol(rest)?;
+    Ok(Command::BoundVar(idx, i))
+}
+
+// <info> <nidx> <eidx_1> <eidx_2> of #EP and #EL
+fn parse_binder(s: &str) -> LineResult<(InfoAnnotation, NameId, ExprId, ExprId)> {
+    let (info, rest) = next(s).ok_or("Expecting info")?;
+    let info = parse_info_annotation(info)?;
+    let (nidx, rest) = next_idx(rest).ok_or("Expecting index")?;
+    let (eidx1, rest) = next_idx(rest).ok_or("Expecting index")?;
+    let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok((info, nidx, eidx1, eidx2))
+}
+
+// <eidx'> #EP <info> <nidx> <eidx_1> <eidx_2>
+fn parse_ep(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (info, nidx, eidx1, eidx2) = parse_binder(s)?;
+    Ok(Command::Pi(idx, info, nidx, eidx1, eidx2))
+}
+
+// <eidx'> #EL <info> <nidx> <eidx_1> <eidx_2>
+fn parse_el(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (info, nidx, eidx1, eidx2) = parse_binder(s)?;
+    Ok(Command::Lambda(idx, info, nidx, eidx1, eidx2))
+}
+
+// <eidx'> #EC <nidx> <uidx>*
+fn parse_ec(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
+    Ok(Command::Constant(idx, nidx, rest_idxs(rest)?))
+}
+
+// <eidx'> #EA <eidx_1> <eidx_2>
+fn parse_ea(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (eidx1, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::FunAppl(idx, eidx1, eidx2))
+}
+
+// <eidx'> #EZ <nidx> <eidx_1> <eidx_2> <eidx_3>
+fn parse_ez(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (eidx1, rest) = next_idx(rest).ok_or("Expecting index")?;
+    let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
+    let (eidx3, rest) = next_idx(rest).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::Let(idx, nidx, eidx1, eidx2, eidx3))
+}
+
+// <eidx'> #EJ <nidx> <integer> <eidx>
+fn parse_ej(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (i, rest) = next_idx(rest).ok_or("Expecting integer")?;
+    let (eidx, rest) = next_idx(rest).ok_or("Expecting index")?;
+    check_eol(rest)?;
+    Ok(Command::Proj(idx, nidx, i, eidx))
+}
 
-    fn parse_ni(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (p, rest) = next_idx(s).ok_or("Expecting index")?;
-        let (i, rest) = next_idx(rest).ok_or("Expecting integer")?;
-        check_eol(rest)?;
-        self.env.add_name(idx, NameItem::Int(i), p);
-        self.post_add_name(idx);
-        Ok(())
+// <eidx'> #ELN <integer>
+fn parse_eln(idx: ExprId, s: &str) -> LineResult<Command> {
+    let (n, rest) = next(s).ok_or("Expecting integer")?;
+    if !n.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(LineError::from("Expecting integer"));
     }
+    check_eol(rest)?;
+    Ok(Command::NatLit(idx, n.to_string()))
+}
+
+// <eidx'> #ELS <hex>*
+fn parse_els(idx: ExprId, s: &str) -> LineResult<Command> {
+    Ok(Command::StrLit(idx, parse_hex_string(s)?))
+}
+
+// #DEF <nidx> <eidx_1> <edix_2> <nidx*>
+fn parse_def(s: &str) -> LineResult<Command> {
+    let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (eidx1, rest) = next_idx(rest).ok_or("Expecting index")?;
+    let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
+    Ok(Command::Definition(nidx, eidx1, eidx2, rest_idxs(rest)?))
+}
 
-    fn parse_ns(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (p, rest) = next_idx(s).ok_or("Expecting index")?;
-        let (s, rest) = next(rest).ok_or("Expecting identifier")?;
-        check_eol(rest)?;
-        self.env.add_name(idx, NameItem::Str(s.to_string()), p);
-        self.post_add_name(idx);
-        Ok(())
-    }
+// #IND <num> <nidx> <eidx> <num_intros> <intro>* <nidx*>
+fn parse_ind(s: &str) -> LineResult<Command> {
+    let (num, rest) = next_idx(s).ok_or("Expecting number")?;
+    let (nidx, rest) = next_idx(rest).ok_or("Expecting index")?;
+    let (eidx, rest) = next_idx(rest).ok_or("Expecting index")?;
+    let (num_intros, rest) = next_idx::<usize>(rest).ok_or("Expecting number")?;
+    let mut rest = rest;
+    let mut intros: Vec<(NameId, ExprId)> = vec![];
+    for _ in 0..num_intros {
+        let (ni, r) = next_idx(rest).ok_or("Expecting index")?;
+        let (ei, r) = next_idx(r).ok_or("Expecting index")?;
+        intros.push((ni, ei));
+        rest = r;
+    }
+    Ok(Command::Inductive(
+        num,
+        nidx,
+        eidx,
+        intros,
+        rest_idxs(rest)?,
+    ))
+}
 
-    /*
-     * <uidx'> #US  <uidx>
-     * <uidx'> #UM  <uidx_1> <uidx_2>
-     * <uidx'> #UIM <uidx_1> <uidx_2>
-     * <uidx'> #UP  <nidx>
-     */
+// #AX <nidx> <eidx> <nidx*>
+fn parse_ax(s: &str) -> LineResult<Command> {
+    let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (eidx, rest) = next_idx(rest).ok_or("Expecting index")?;
+    Ok(Command::Axiom(nidx, eidx, rest_idxs(rest)?))
+}
 
-    fn post_add_level(&self, idx: Index) {
-        println!("Level {}: {}", idx, self.env.level_to_string(idx));
-    }
-
-    fn parse_us(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (u, rest) = next_idx(s).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_level_succ(idx, u);
-        self.post_add_level(idx);
-        Ok(())
-    }
+// #QUOT
+fn parse_quot(s: &str) -> LineResult<Command> {
+    check_eol(s)?;
+    Ok(Command::Quot)
+}
 
-    fn parse_um(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (u1, rest) = next_idx(s).ok_or("Expecting index")?;
-        let (u2, rest) = next_idx(rest).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_level_max(idx, u1, u2);
-        self.post_add_level(idx);
-        Ok(())
-    }
-
-    fn parse_uim(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (u1, rest) = next_idx(s).ok_or("Expecting index")?;
-        let (u2, rest) = next_idx(rest).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_level_imax(idx, u1, u2);
-        self.post_add_level(idx);
-        Ok(())
-    }
+// #PREFIX, #INFIX or #POSTFIX <nidx> <prec> <token>
+fn parse_notation(kind: NotationKind, s: &str) -> LineResult<Command> {
+    let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
+    let (prec, rest) = next_idx(rest).ok_or("Expecting number")?;
+    let (token, rest) = next(rest).ok_or("Expecting token")?;
+    check_eol(rest)?;
+    Ok(Command::Notation(kind, nidx, prec, token.to_string()))
+}
 
-    fn parse_up(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (n, rest) = next_idx(s).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_level_param(idx, n);
-        self.post_add_level(idx);
-        Ok(())
-    }
-
-    fn post_add_expr(&self, idx: Index) {
-        println!("Expr {}: {}", idx, self.env.expr_to_string(idx));
-    }
-
-    fn parse_es(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (u, rest) = next_idx(s).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_expr_sort(idx, u);
-        self.post_add_expr(idx);
-        Ok(())
-    }
-
-    fn parse_ev(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (i, rest) = next_idx(s).ok_or("Expecting integer")?;
-        check_eol(rest)?;
-        self.env.add_expr_bound_var(idx, i);
-        self.post_add_expr(idx);
-        Ok(())
-    }
-
-    // <eidx'> #EP <info> <nidx> <eidx_1> <eidx_2>
-    fn parse_ep(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (info, rest) = next(s).ok_or("Expecting info")?;
-        let info = parse_info_annotation(info)?;
-        let (nidx, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let (eidx1, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_expr_pi(idx, info, nidx, eidx1, eidx2);
-        self.post_add_expr(idx);
-        Ok(())
-    }
-
-    // <eidx'> #EL <info> <nidx> <eidx_1> <eidx_2>
-    fn parse_el(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (info, rest) = next(s).ok_or("Expecting info")?;
-        let info = parse_info_annotation(info)?;
-        let (nidx, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let (eidx1, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_expr_lambda(idx, info, nidx, eidx1, eidx2);
-        self.post_add_expr(idx);
-        Ok(())
-    }
-
-    // <eidx'> #EC <nidx> <uidx>*
-    fn parse_ec(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
-        let mut level_idxs: Vec<Index> = vec![];
-        let mut rest = rest;
-        while let Some((ni, r)) = next_idx(rest) {
-            level_idxs.push(ni);
-            rest = r;
-        }
-        check_eol(rest)?;
-        self.env.add_expr_constant(idx, nidx, level_idxs);
-        self.post_add_expr(idx);
-        Ok(())
-    }
-
-    // <eidx'> #EA <eidx_1> <eidx_2>
-    fn parse_ea(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (eidx1, rest) = next_idx(s).ok_or("Expecting index")?;
-        let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
-        check_eol(rest)?;
-        self.env.add_expr_funappl(idx, eidx1, eidx2);
-        self.post_add_expr(idx);
-        Ok(())
-    }
-
-    fn post_add_declaration(&self, idx: Index) {
-        println!("Declaration {}: {}", idx, self.env.decl_to_string(idx));
-    }
-
-    // #DEF <nidx> <eidx_1> <edix_2> <nidx*>
-    fn parse_def(&mut self, s: &str) -> LineResult<()> {
-        let (nidx, rest) = next_idx(s).ok_or("Expecting index")?;
-        let (eidx1, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let (eidx2, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let mut level_nidxs: Vec<Index> = vec![];
-        let mut rest = rest;
-        while let Some((ni, r)) = next_idx(rest) {
-            level_nidxs.push(ni);
-            rest = r;
-        }
-        check_eol(rest)?;
-        self.env.add_definition(nidx, eidx1, eidx2, level_nidxs);
-        self.post_add_declaration(nidx);
-        Ok(())
-    }
-
-    // #IND <num> <nidx> <eidx> <num_intros> <intro>* <nidx*>
-    fn parse_ind(&mut self, s: &str) -> LineResult<()> {
-        let (num, rest) = next_idx(s).ok_or("Expecting number")?;
-        let (nidx, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let (eidx, rest) = next_idx(rest).ok_or("Expecting index")?;
-        let (num_intros, rest) = next_idx(rest).ok_or("Expecting number")?;
-        let mut rest = rest;
-        let mut intros: Vec<(Index, Index)> = vec![];
-        for _ in 0..num_intros {
-            let (ni, r) = next_idx(rest).ok_or("Expecting index")?;
-            let (ei, r) = next_idx(r).ok_or("Expecting index")?;
-            intros.push((ni, ei));
-            rest = r;
-        }
-        let mut level_nidxs: Vec<Index> = vec![];
-        while let Some((ni, r)) = next_idx(rest) {
-            level_nidxs.push(ni);
-            rest = r;
-        }
-        check_eol(rest)?;
-        self.env.add_inductive(num, nidx, eidx, intros, level_nidxs);
-        self.post_add_declaration(nidx);
-        Ok(())
-    }
-
-    fn parse_index_command(&mut self, idx: Index, s: &str) -> LineResult<()> {
-        let (cmd, rest) = next(s).ok_or("Expecting index command")?;
-        match cmd {
-            "#NI" => self.parse_ni(idx, rest),
-            "#NS" => self.parse_ns(idx, rest),
-
-            "#US" => self.parse_us(idx, rest),
-            "#UM" => self.parse_um(idx, rest),
-            "#UIM" => self.parse_uim(idx, rest),
-            "#UP" => self.parse_up(idx, rest),
-
-            "#ES" => self.parse_es(idx, rest),
-            "#EV" => self.parse_ev(idx, rest),
-            "#EP" => self.parse_ep(idx, rest),
-            "#EL" => self.parse_el(idx, rest),
-            "#EC" => self.parse_ec(idx, rest),
-            "#EA" => self.parse_ea(idx, rest),
-            "#EJ" => todo!("#EJ"),
-            "#ELN" => todo!("#ELN"),
-            "#ELS" => todo!("#ELS"),
-            "#EZ" => todo!("#EZ"),
-
-            _ => return Err(LineError::from("Unsupported index command")),
-        }?;
-        Ok(())
-    }
-
-    fn parse_command(&mut self, cmd: &str, rest: &str) -> LineResult<()> {
-        match cmd {
-            "#DEF" => self.parse_def(rest),
-            "#AX" => todo!("#AX"),
-            "#IND" => self.parse_ind(rest),
-            "#QUOT" => todo!("#QUOT"),
-            "#PREFIX" => todo!("#PREFIX"),
-            "#POSTFIX" => todo!("#POSTFIX"),
-            "#INFIX" => todo!("#INFIX"),
-
-            _ => return Err(LineError::from("Unsupported command")),
-        }?;
-        Ok(())
+fn parse_index_command(idx: u32, s: &str) -> LineResult<Command> {
+    let (cmd, rest) = next(s).ok_or("Expecting index command")?;
+    match cmd {
+        "#NI" => parse_ni(idx.into(), rest),
+        "#NS" => parse_ns(idx.into(), rest),
+
+        "#US" => parse_us(idx.into(), rest),
+        "#UM" => parse_um(idx.into(), rest),
+        "#UIM" => parse_uim(idx.into(), rest),
+        "#UP" => parse_up(idx.into(), rest),
+
+        "#ES" => parse_es(idx.into(), rest),
+        "#EV" => parse_ev(idx.into(), rest),
+        "#EP" => parse_ep(idx.into(), rest),
+        "#EL" => parse_el(idx.into(), rest),
+        "#EC" => parse_ec(idx.into(), rest),
+        "#EA" => parse_ea(idx.into(), rest),
+        "#EJ" => parse_ej(idx.into(), rest),
+        "#ELN" => parse_eln(idx.into(), rest),
+        "#ELS" => parse_els(idx.into(), rest),
+        "#EZ" => parse_ez(idx.into(), rest),
+
+        _ => Err(LineError::from("Unsupported index command")),
     }
+}
 
-    fn parse_line(&mut self, line: &str) -> LineResult<()> {
-        let (first, rest) = next(line).ok_or("Expecting index or command")?;
-        match first.parse::<usize>() {
-            Ok(idx) => self.parse_index_command(idx, rest),
-            Err(_) => self.parse_command(first, rest),
+fn parse_command(cmd: &str, rest: &str) -> LineResult<Command> {
+    match cmd {
+        "#DEF" => parse_def(rest),
+        "#AX" => parse_ax(rest),
+        "#IND" => parse_ind(rest),
+        "#QUOT" => parse_quot(rest),
+        "#PREFIX" => parse_notation(NotationKind::Prefix, rest),
+        "#POSTFIX" => parse_notation(NotationKind::Postfix, rest),
+        "#INFIX" => parse_notation(NotationKind::Infix, rest),
+
+        _ => Err(LineError::from("Unsupported command")),
+    }
+}
+
+fn parse_line(line: &str) -> LineResult<Command> {
+    let (first, rest) = next(line).ok_or("Expecting index or command")?;
+    match first.parse::<u32>() {
+        Ok(idx) => parse_index_command(idx, rest),
+        Err(_) => parse_command(first, rest),
+    }
+}
+
+// Add the item of a parsed line to the environment
+fn apply(env: &mut Environment, observer: &mut dyn Observer, cmd: Command) -> LineResult<()> {
+    match cmd {
+        Command::Name(idx, item, p) => {
+            env.add_name(idx, item, p)?;
+            observer.on_name(env, idx);
+        }
+        Command::LevelSucc(idx, u) => {
+            env.add_level_succ(idx, u)?;
+            observer.on_level(env, idx);
         }
+        Command::LevelMax(idx, u1, u2) => {
+            env.add_level_max(idx, u1, u2)?;
+            observer.on_level(env, idx);
+        }
+        Command::LevelIMax(idx, u1, u2) => {
+            env.add_level_imax(idx, u1, u2)?;
+            observer.on_level(env, idx);
+        }
+        Command::LevelParam(idx, n) => {
+            env.add_level_param(idx, n)?;
+            observer.on_level(env, idx);
+        }
+        Command::Sort(idx, u) => {
+            env.add_expr_sort(idx, u)?;
+            observer.on_expr(env, idx);
+        }
+        Command::BoundVar(idx, i) => {
+            env.add_expr_bound_var(idx, i)?;
+            observer.on_expr(env, idx);
+        }
+        Command::Pi(idx, info, nidx, eidx1, eidx2) => {
+            env.add_expr_pi(idx, info, nidx, eidx1, eidx2)?;
+            observer.on_expr(env, idx);
+        }
+        Command::Lambda(idx, info, nidx, eidx1, eidx2) => {
+            env.add_expr_lambda(idx, info, nidx, eidx1, eidx2)?;
+            observer.on_expr(env, idx);
+        }
+        Command::Constant(idx, nidx, levels) => {
+            env.add_expr_constant(idx, nidx, levels)?;
+            observer.on_expr(env, idx);
+        }
+        Command::FunAppl(idx, eidx1, eidx2) => {
+            env.add_expr_funappl(idx, eidx1, eidx2)?;
+            observer.on_expr(env, idx);
+        }
+        Command::Let(idx, nidx, eidx1, eidx2, eidx3) => {
+            env.add_expr_let(idx, nidx, eidx1, eidx2, eidx3)?;
+            observer.on_expr(env, idx);
+        }
+        Command::Proj(idx, nidx, i, eidx) => {
+            env.add_expr_proj(idx, nidx, i, eidx)?;
+            observer.on_expr(env, idx);
+        }
+        Command::NatLit(idx, digits) => {
+            env.add_expr_nat_lit(idx, digits)?;
+            observer.on_expr(env, idx);
+        }
+        Command::StrLit(idx, s) => {
+            env.add_expr_str_lit(idx, s)?;
+            observer.on_expr(env, idx);
+        }
+        Command::Definition(nidx, eidx1, eidx2, params) => {
+            env.add_definition(nidx, eidx1, eidx2, params)?;
+            observer.on_decl(env, nidx);
+        }
+        Command::Inductive(num, nidx, eidx, intros, params) => {
+            env.add_inductive(num, nidx, eidx, intros, params)?;
+            observer.on_decl(env, nidx);
+        }
+        Command::Axiom(nidx, eidx, params) => {
+            env.add_axiom(nidx, eidx, params)?;
+            observer.on_decl(env, nidx);
+        }
+        Command::Quot => env.add_quot()?,
+        Command::Notation(kind, nidx, prec, token) => {
+            let i = env.add_notation(kind, nidx, prec, token)?;
+            observer.on_notation(env, i);
+        }
+    }
+    Ok(())
+}
+
+impl<'o> Parser<'o> {
+    fn new(options: &ParseOptions, observer: &'o mut dyn Observer) -> Self {
+        Self {
+            env: Environment::with_dialect(options.dialect),
+            observer,
+        }
+    }
+
+    fn apply(&mut self, cmd: Command) -> LineResult<()> {
+        apply(&mut self.env, self.observer, cmd)
+    }
+
+    fn parse_line(&mut self, line: &str) -> LineResult<()> {
+        self.apply(parse_line(line)?)
     }
 
     fn get_environment(self) -> Environment {
@@ -336,28 +545,164 @@ impl Parser {
     }
 }
 
-pub fn parse_lines<R: Read>(file: R) -> std::result::Result<Environment, ParseError> {
+// Parse an export, stopping at the first error, which is also reported to
+// the observer
+pub fn parse_lines<R: Read>(
+    file: R,
+    options: &ParseOptions,
+    observer: &mut dyn Observer,
+) -> std::result::Result<Environment, ParseError> {
     let reader = BufReader::new(file);
 
-    let mut parser = Parser::new();
-    let mut line_no = 1;
+    let mut parser = Parser::new(options, observer);
 
-    for line in reader.lines() {
-        let line = line.map_err(|e| ParseError::new(LineError::from(e), line_no))?;
+    for (line, line_no) in reader.lines().zip(1..) {
+        let result = line
+            .map_err(LineError::from)
+            .and_then(|line| parser.parse_line(&line));
+        if let Err(line_error) = result {
+            let error = ParseError::new(line_error, line_no);
+            parser.observer.on_error(&error);
+            return Err(error);
+        }
+    }
 
-        parser
-            .parse_line(&line)
-            .map_err(|line_error| ParseError::new(line_error, line_no))?;
+    Ok(parser.get_environment())
+}
+
+/*
+ * Like parse_lines, but each declaration is also checked, on a thread of its
+ * own, as soon as its line has been added to the environment. Lines are read
+ * and parsed on another thread and added to the environment on the calling
+ * one. The checker only holds the environment, read-only, for one
+ * declaration at a time, and otherwise runs while the rest of the input is
+ * parsed. The results are returned in export order; the observer sees each
+ * one as it is found.
+ */
+pub fn parse_lines_pipelined<R: Read + Send>(
+    file: R,
+    options: &ParseOptions,
+    observer: &mut (dyn Observer + Send),
+) -> std::result::Result<(Environment, Vec<DeclResult>), ParseError> {
+    // Lines are sent in batches to keep the synchronization overhead low
+    const BATCH_SIZE: usize = 1024;
+
+    let env = RwLock::new(Environment::with_dialect(options.dialect));
+    let observer = Mutex::new(observer);
+    let results = thread::scope(|scope| {
+        let (sender, receiver) = sync_channel::<Vec<(usize, LineResult<Command>)>>(16);
+        scope.spawn(move || {
+            let reader = BufReader::new(file);
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            for (line, line_no) in reader.lines().zip(1..) {
+                let command = line.map_err(LineError::from).and_then(|l| parse_line(&l));
+                let failed = command.is_err();
+                // A declaration ends its batch, so that it is checked without
+                // waiting for more input
+                let decl = matches!(
+                    command,
+                    Ok(Command::Definition(..) | Command::Inductive(..) | Command::Axiom(..))
+                );
+                batch.push((line_no, command));
+                // Stop at the first error, or when the receiver has given up
+                if failed || decl || batch.len() == BATCH_SIZE {
+                    if sender.send(batch).is_err() || failed {
+                        return;
+                    }
+                    batch = Vec::with_capacity(BATCH_SIZE);
+                }
+            }
+            let _ = sender.send(batch);
+        });
+
+        let (decl_sender, decl_receiver) = channel::<NameId>();
+        let checker = scope.spawn(|| {
+            decl_receiver
+                .into_iter()
+                .map(|nidx| {
+                    let env = env.read().unwrap();
+                    let result = check_declaration_timed(&env, nidx);
+                    observer.lock().unwrap().on_decl_checked(&env, &result);
+                    result
+                })
+                .collect::<Vec<_>>()
+        });
+
+        for batch in receiver {
+            let mut env = env.write().unwrap();
+            let mut observer = observer.lock().unwrap();
+            let count = env.declarations().len();
+            for (line_no, command) in batch {
+                if let Err(line_error) = command.and_then(|c| apply(&mut env, *observer, c)) {
+                    let error = ParseError::new(line_error, line_no);
+                    observer.on_error(&error);
+                    return Err(error);
+                }
+            }
+            for nidx in &env.declarations()[count..] {
+                decl_sender.send(*nidx).expect("Checker thread stopped");
+            }
+        }
+        drop(decl_sender);
+        Ok(checker.join().expect("Checker thread panicked"))
+    })?;
+    Ok((env.into_inner().unwrap(), results))
+}
 
-        line_no += 1;
+/*
+ * Parse all lines, recording an error for every line that fails instead of
+ * stopping at the first one. A failing line is skipped, and so is every line
+ * that refers to an index defined by a skipped line. Each error is also
+ * reported to the observer as it occurs.
+ */
+pub fn parse_lines_keep_going<R: Read>(
+    file: R,
+    options: &ParseOptions,
+    observer: &mut dyn Observer,
+) -> (Environment, Vec<ParseError>) {
+    let reader = BufReader::new(file);
+
+    let mut parser = Parser::new(options, observer);
+    let mut errors: Vec<ParseError> = vec![];
+    // Failed index to the line number of the error that caused it
+    let mut failed: HashMap<IndexKind, usize> = HashMap::new();
+
+    for (line, line_no) in reader.lines().zip(1..) {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                let error = ParseError::new(LineError::from(e), line_no);
+                parser.observer.on_error(&error);
+                errors.push(error);
+                break;
+            }
+        };
+
+        if let Err(line_error) = parser.parse_line(&line) {
+            let (line_error, cause) = match line_error
+                .missing_index()
+                .and_then(|kind| failed.get(&kind))
+            {
+                Some(cause) => (LineError::Dependent(*cause), *cause),
+                None => (line_error, line_no),
+            };
+            if let Some(kind) = defined_index(&line) {
+                failed.insert(kind, cause);
+            }
+            let error = ParseError::new(line_error, line_no);
+            parser.observer.on_error(&error);
+            errors.push(error);
+        }
     }
 
-    Ok(parser.get_environment())
+    (parser.get_environment(), errors)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::mpsc::{Receiver, Sender};
+    use std::time::Duration;
 
     #[test]
     fn test_next() {
@@ -371,15 +716,216 @@ mod tests {
         assert!(res.is_none());
     }
 
+    fn parse_str(s: &str, dialect: Dialect) -> Result<Environment, ParseError> {
+        let options = ParseOptions { dialect };
+        parse_lines(s.as_bytes(), &options, &mut ())
+    }
+
+    #[test]
+    fn test_lean3_dialect() {
+        let export = "1 #NS 0 nat\n\
+                      2 #NS 1 add\n\
+                      #QUOT\n\
+                      #INFIX 2 65 +\n";
+        assert!(parse_str(export, Dialect::Lean3).is_ok());
+        let err = parse_str(export, Dialect::Lean4).err().unwrap();
+        assert_eq!(err.line_no, 4);
+
+        let export = "1 #NS 0 prod\n\
+                      0 #EV 0\n\
+                      1 #EJ 1 0 0\n";
+        assert!(parse_str(export, Dialect::Lean4).is_ok());
+        let err = parse_str(export, Dialect::Lean3).err().unwrap();
+        assert_eq!(err.line_no, 3);
+
+        let err = parse_str("0 #ELN 42\n", Dialect::Lean3).err().unwrap();
+        assert_eq!(err.line_no, 1);
+    }
+
+    #[test]
+    fn test_env_error() {
+        let export = "1 #NS 0 foo\n\
+                      0 #EV 0\n\
+                      1 #EA 0 2\n";
+        let err = parse_str(export, Dialect::Lean4).err().unwrap();
+        assert_eq!(err.line_no, 3);
+        assert!(matches!(
+            err.line_error,
+            LineError::Env(EnvError::UnknownExpr(idx)) if idx.index() == 2
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Parse error at line 3: Unknown expression index 2"
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        let env = parse_str("0 #ELN 42\n1 #ELS 68 69\n", Dialect::Lean4)
+            .ok()
+            .unwrap();
+        assert_eq!(env.expr_to_string(0.into()), "42");
+        assert_eq!(env.expr_to_string(1.into()), "\"hi\"");
+    }
+
+    #[test]
+    fn test_keep_going() {
+        let export = "1 #NS 0 foo\n\
+                      0 #EV 0\n\
+                      1 #EA 0 7\n\
+                      2 #EA 1 0\n\
+                      3 #EL #BD 1 0 2\n\
+                      4 #EX\n\
+                      5 #EA 0 0\n";
+        let (env, errors) =
+            parse_lines_keep_going(export.as_bytes(), &ParseOptions::default(), &mut ());
+        let lines = errors.iter().map(|e| e.line_no).collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 4, 5, 6]);
+        let dependent = errors.iter().map(|e| e.is_dependent()).collect::<Vec<_>>();
+        assert_eq!(dependent, vec![false, true, true, false]);
+        assert!(matches!(errors[2].line_error, LineError::Dependent(3)));
+        assert_eq!(env.expr_to_string(5.into()), "(<0> <0>)");
+    }
+
+    #[test]
+    fn test_pipelined() {
+        let export = "1 #NS 0 a\n\
+                      2 #NS 0 b\n\
+                      0 #ES 0\n\
+                      #AX 1 0\n\
+                      1 #EC 1\n\
+                      #DEF 2 0 1\n";
+        let mut events = Events::default();
+        let (env, results) =
+            parse_lines_pipelined(export.as_bytes(), &ParseOptions::default(), &mut events)
+                .ok()
+                .unwrap();
+        let decls = events.0.iter().filter(|e| e.starts_with("decl"));
+        assert_eq!(decls.collect::<Vec<_>>(), ["decl a 1", "decl b 2"]);
+        assert_eq!(env.declarations().len(), 2);
+        let checked = results.iter().map(|r| r.nidx).collect::<Vec<_>>();
+        assert_eq!(checked, env.declarations());
+        assert!(results.iter().all(|r| r.result.is_ok()));
+
+        let export = "1 #NS 0 a\n\
+                      0 #ES 0\n\
+                      #AX 1 0\n\
+                      1 #EX\n\
+                      #AX 1 0\n";
+        let mut events = Events::default();
+        let err = parse_lines_pipelined(export.as_bytes(), &ParseOptions::default(), &mut events)
+            .err()
+            .unwrap();
+        assert_eq!(err.line_no, 4);
+        assert_eq!(events.0[2..], ["decl a 1", "error 4"]);
+    }
+
+    // Input whose second part is only given once a declaration has been
+    // checked
+    struct Stalled {
+        first: &'static [u8],
+        rest: &'static [u8],
+        checked: Receiver<String>,
+    }
+
+    impl Read for Stalled {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.first.is_empty() && !self.rest.is_empty() {
+                let timeout = Duration::from_secs(10);
+                if self.checked.recv_timeout(timeout).is_err() {
+                    return Err(std::io::Error::other("Nothing checked while parsing"));
+                }
+                self.first = std::mem::take(&mut self.rest);
+            }
+            self.first.read(buf)
+        }
+    }
+
+    struct Checked(Sender<String>);
+
+    impl Observer for Checked {
+        fn on_decl_checked(&mut self, env: &Environment, result: &DeclResult) {
+            let _ = self.0.send(env.name_to_string(result.nidx));
+        }
+    }
+
+    #[test]
+    fn test_pipelined_overlap() {
+        let (sender, receiver) = channel();
+        let input = Stalled {
+            first: b"1 #NS 0 a\n0 #ES 0\n#AX 1 0\n",
+            rest: b"2 #NS 0 b\n1 #EC 1\n#DEF 2 0 1\n",
+            checked: receiver,
+        };
+        // The rest of the input is only read after a has been checked
+        let (env, results) =
+            parse_lines_pipelined(input, &ParseOptions::default(), &mut Checked(sender))
+                .ok()
+                .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(env.name_to_string(results[0].nidx), "a");
+    }
+
+    // Observer recording what it is told, with the number of expressions at
+    // the time of each declaration
+    #[derive(Default)]
+    struct Events(Vec<String>);
+
+    impl Observer for Events {
+        fn on_name(&mut self, env: &Environment, idx: NameId) {
+            self.0.push(format!("name {}", env.name_to_string(idx)));
+        }
+
+        fn on_level(&mut self, env: &Environment, idx: LevelId) {
+            self.0.push(format!("level {}", env.level_to_string(idx)));
+        }
+
+        fn on_expr(&mut self, env: &Environment, idx: ExprId) {
+            self.0.push(format!("expr {}", env.expr_to_string(idx)));
+        }
+
+        fn on_decl(&mut self, env: &Environment, idx: NameId) {
+            let name = env.name_to_string(idx);
+            self.0.push(format!("decl {} {}", name, env.expr_count()));
+        }
+
+        fn on_error(&mut self, error: &ParseError) {
+            self.0.push(format!("error {}", error.line_no));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let export = "1 #NS 0 a\n\
+                      1 #UP 1\n\
+                      0 #ES 1\n\
+                      #AX 1 0 1\n\
+                      1 #EX\n\
+                      2 #NS 1 b\n";
+        let mut events = Events::default();
+        parse_lines_keep_going(export.as_bytes(), &ParseOptions::default(), &mut events);
+        assert_eq!(
+            events.0,
+            [
+                "name a",
+                "level a",
+                "expr Sort a",
+                "decl a 1",
+                "error 5",
+                "name a.b"
+            ]
+        );
+    }
+
     #[test]
     fn test_next_idx() {
-        let res = next_idx(" 1  234 56  ");
+        let res = next_idx::<usize>(" 1  234 56  ");
         assert_eq!(res, Some((1, "  234 56  ")));
-        let res = next_idx(res.unwrap().1);
+        let res = next_idx::<usize>(res.unwrap().1);
         assert_eq!(res, Some((234, " 56  ")));
-        let res = next_idx(res.unwrap().1);
+        let res = next_idx::<usize>(res.unwrap().1);
         assert_eq!(res, Some((56, "  ")));
-        let res = next_idx(res.unwrap().1);
+        let res = next_idx::<usize>(res.unwrap().1);
         assert!(res.is_none());
     }
 }
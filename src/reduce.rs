@@ -0,0 +1,551 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use super::environment::{Constant, Decl, Environment, Expr, ExprId, Level, LevelId, NameId};
+
+/*
+ * Reduction by beta, delta (unfolding definitions), zeta (let) and
+ * projections of constructor applications. Recursors are not reduced.
+ * New terms are added to the environment. Besides the reduction steps
+ * themselves, every subterm visited while substituting counts as a step, so
+ * the step limit bounds the total work. Closed subterms, and subterms
+ * without universe parameters, are skipped without being visited.
+ */
+
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ReduceError {
+    StepLimit(usize),
+}
+
+impl fmt::Display for ReduceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReduceError::StepLimit(steps) => {
+                write!(f, "Reduction did not finish within {} steps", steps)
+            }
+        }
+    }
+}
+
+pub type ReduceResult<T> = Result<T, ReduceError>;
+
+pub struct ReduceOptions {
+    pub delta: bool,
+    pub max_steps: usize,
+}
+
+impl Default for ReduceOptions {
+    fn default() -> Self {
+        Self {
+            delta: true,
+            max_steps: 1_000_000,
+        }
+    }
+}
+
+// What a substitution does with a subterm
+enum Visit {
+    // left as is, without counting a step
+    Unchanged,
+    Replace(ExprId),
+    // rebuilt from its transformed children
+    Children,
+}
+
+pub struct Reducer<'a> {
+    env: &'a mut Environment,
+    options: ReduceOptions,
+    steps: usize,
+    normal_forms: HashMap<ExprId, ExprId>,
+}
+
+impl<'a> Reducer<'a> {
+    pub fn new(env: &'a mut Environment, options: ReduceOptions) -> Self {
+        Self {
+            env,
+            options,
+            steps: 0,
+            normal_forms: HashMap::new(),
+        }
+    }
+
+    fn step(&mut self) -> ReduceResult<()> {
+        self.steps += 1;
+        if self.steps > self.options.max_steps {
+            return Err(ReduceError::StepLimit(self.options.max_steps));
+        }
+        Ok(())
+    }
+
+    // Head and arguments of an application
+    fn spine(&self, eidx: ExprId) -> (ExprId, Vec<ExprId>) {
+        let mut args = vec![];
+        let mut e = eidx;
+        while let Expr::FunAppl(f, a) = self.env.expr(e) {
+            args.push(*a);
+            e = *f;
+        }
+        args.reverse();
+        (e, args)
+    }
+
+    fn mk_app(&mut self, f: ExprId, args: &[ExprId]) -> ExprId {
+        args.iter()
+            .fold(f, |f, a| self.env.push_expr(Expr::FunAppl(f, *a)))
+    }
+
+    // Rebuild `expr` with new children, unless they are unchanged
+    fn rebuild(&mut self, eidx: ExprId, children: &[ExprId]) -> ExprId {
+        let expr = self.env.expr(eidx);
+        if expr.children() == children {
+            return eidx;
+        }
+        let new_expr = match (expr, children) {
+            (Expr::FunAppl(_, _), [f, a]) => Expr::FunAppl(*f, *a),
+            (Expr::Lambda(info, n, _, _), [d, b]) => Expr::Lambda(*info, *n, *d, *b),
+            (Expr::Pi(info, n, _, _), [d, b]) => Expr::Pi(*info, *n, *d, *b),
+            (Expr::Let(n, _, _, _), [t, v, b]) => Expr::Let(*n, *t, *v, *b),
+            (Expr::Proj(n, i, _), [s]) => Expr::Proj(*n, *i, *s),
+            _ => unreachable!("Children do not match expression"),
+        };
+        self.env.push_expr(new_expr)
+    }
+
+    // Number of binders a child is under, relative to its parent
+    fn binder_offsets(&self, eidx: ExprId) -> Vec<usize> {
+        match self.env.expr(eidx) {
+            Expr::Lambda(..) | Expr::Pi(..) => vec![0, 1],
+            Expr::Let(..) => vec![0, 0, 1],
+            expr => vec![0; expr.children().len()],
+        }
+    }
+
+    /*
+     * Rebuild `root` bottom-up. `visit` is given each subterm with the number
+     * of binders it is under, starting from `depth` (or always `depth` if
+     * `binders` is false), and decides what becomes of it. Results are cached
+     * by subterm and depth. The traversal keeps its own stack, as terms can
+     * be nested arbitrarily deep.
+     */
+    fn transform<F>(
+        &mut self,
+        root: ExprId,
+        depth: usize,
+        binders: bool,
+        cache: &mut HashMap<(ExprId, usize), ExprId>,
+        mut visit: F,
+    ) -> ExprId
+    where
+        F: FnMut(&mut Self, ExprId, usize) -> Visit,
+    {
+        // subterm, depth, and whether its children are done
+        let mut stack = vec![(root, depth, false)];
+        let mut results = vec![];
+        while let Some((eidx, depth, expanded)) = stack.pop() {
+            let children = self.env.expr(eidx).children();
+            if expanded {
+                let new_children = results.split_off(results.len() - children.len());
+                let result = self.rebuild(eidx, &new_children);
+                cache.insert((eidx, depth), result);
+                results.push(result);
+                continue;
+            }
+            if let Some(r) = cache.get(&(eidx, depth)) {
+                results.push(*r);
+                continue;
+            }
+            match visit(self, eidx, depth) {
+                Visit::Unchanged => {
+                    results.push(eidx);
+                    continue;
+                }
+                Visit::Replace(r) => {
+                    self.steps += 1;
+                    cache.insert((eidx, depth), r);
+                    results.push(r);
+                    continue;
+                }
+                Visit::Children => self.steps += 1,
+            }
+            let offsets = match binders {
+                true => self.binder_offsets(eidx),
+                false => vec![0; children.len()],
+            };
+            stack.push((eidx, depth, true));
+            for (c, o) in children.into_iter().zip(offsets).rev() {
+                stack.push((c, depth + o, false));
+            }
+        }
+        results.pop().expect("No result")
+    }
+
+    // Add `shift` to the bound variables of `eidx` that are at least `depth`
+    fn lift(
+        &mut self,
+        eidx: ExprId,
+        shift: usize,
+        depth: usize,
+        cache: &mut HashMap<(ExprId, usize), ExprId>,
+    ) -> ExprId {
+        if shift == 0 {
+            return eidx;
+        }
+        self.transform(eidx, depth, true, cache, |r, e, depth| {
+            // Subterms without bound variables from `depth` on are unchanged
+            if r.env.loose_bvar_range(e) <= depth {
+                return Visit::Unchanged;
+            }
+            match *r.env.expr(e) {
+                Expr::BoundVar(i) => Visit::Replace(r.env.push_expr(Expr::BoundVar(i + shift))),
+                _ => Visit::Children,
+            }
+        })
+    }
+
+    // Replace bound variable 0 of `body` by `val`
+    pub fn instantiate(&mut self, body: ExprId, val: ExprId) -> ExprId {
+        // `val` is lifted by the depth it is substituted at, and lifts by
+        // different amounts cannot share results
+        let mut lift_caches: HashMap<usize, HashMap<_, _>> = HashMap::new();
+        self.transform(body, 0, true, &mut HashMap::new(), |r, e, depth| {
+            if r.env.loose_bvar_range(e) <= depth {
+                return Visit::Unchanged;
+            }
+            match *r.env.expr(e) {
+                Expr::BoundVar(i) if i == depth => {
+                    let cache = lift_caches.entry(depth).or_default();
+                    Visit::Replace(r.lift(val, depth, 0, cache))
+                }
+                Expr::BoundVar(i) => Visit::Replace(r.env.push_expr(Expr::BoundVar(i - 1))),
+                _ => Visit::Children,
+            }
+        })
+    }
+
+    fn instantiate_level(
+        &mut self,
+        root: LevelId,
+        params: &[NameId],
+        levels: &[LevelId],
+    ) -> LevelId {
+        // Bottom-up like `transform`, for levels
+        let mut stack = vec![(root, false)];
+        let mut results = vec![];
+        while let Some((uidx, expanded)) = stack.pop() {
+            let level = *self.env.level(uidx);
+            if !expanded {
+                if !self.env.level_has_params(uidx) {
+                    results.push(uidx);
+                    continue;
+                }
+                stack.push((uidx, true));
+                match level {
+                    Level::Succ(u) => stack.push((u, false)),
+                    Level::Max(u1, u2) | Level::IMax(u1, u2) => {
+                        stack.extend([(u2, false), (u1, false)])
+                    }
+                    Level::Zero | Level::Param(_) => {}
+                }
+                continue;
+            }
+            let new_level = match level {
+                Level::Zero => Level::Zero,
+                Level::Param(n) => {
+                    results.push(match params.iter().position(|p| *p == n) {
+                        Some(i) => levels[i],
+                        None => uidx,
+                    });
+                    continue;
+                }
+                Level::Succ(_) => Level::Succ(results.pop().unwrap()),
+                Level::Max(..) | Level::IMax(..) => {
+                    let u2 = results.pop().unwrap();
+                    let u1 = results.pop().unwrap();
+                    match level {
+                        Level::Max(..) => Level::Max(u1, u2),
+                        _ => Level::IMax(u1, u2),
+                    }
+                }
+            };
+            results.push(self.env.push_level(new_level));
+        }
+        results.pop().expect("No result")
+    }
+
+    // Replace the universe parameters `params` by `levels`
+    pub fn instantiate_level_params(
+        &mut self,
+        eidx: ExprId,
+        params: &[NameId],
+        levels: &[LevelId],
+    ) -> ExprId {
+        if params.is_empty() {
+            return eidx;
+        }
+        self.transform(eidx, 0, false, &mut HashMap::new(), |r, e, _| {
+            if !r.env.has_level_params(e) {
+                return Visit::Unchanged;
+            }
+            match r.env.expr(e).clone() {
+                Expr::Sort(u) => {
+                    let u = r.instantiate_level(u, params, levels);
+                    Visit::Replace(r.env.push_expr(Expr::Sort(u)))
+                }
+                Expr::Constant(n, us) => {
+                    let us = us
+                        .iter()
+                        .map(|u| r.instantiate_level(*u, params, levels))
+                        .collect();
+                    Visit::Replace(r.env.push_expr(Expr::Constant(n, us)))
+                }
+                _ => Visit::Children,
+            }
+        })
+    }
+
+    // The field of a constructor application in weak head normal form, if any
+    fn constructor_field(&self, field: usize, sidx: ExprId) -> Option<ExprId> {
+        let (head, args) = self.spine(sidx);
+        if let Expr::Constant(c, _) = self.env.expr(head) {
+            if let Some(Constant::Constructor(ind, _)) = self.env.resolve_constant(*c) {
+                if let Some(Decl::Ind(params, ..)) = self.env.decl(ind) {
+                    return args.get(params.checked_add(field)?).copied();
+                }
+            }
+        }
+        None
+    }
+
+    // Weak head normal form
+    pub fn whnf(&mut self, eidx: ExprId) -> ReduceResult<ExprId> {
+        let mut e = eidx;
+        // Projections whose structure is being reduced, innermost last: the
+        // field, the arguments applied to the projection, and the projection
+        // application itself
+        let mut projs: Vec<(usize, Vec<ExprId>, ExprId)> = vec![];
+        loop {
+            let (head, args) = self.spine(e);
+            match self.env.expr(head).clone() {
+                Expr::Lambda(_, _, _, body) if !args.is_empty() => {
+                    self.step()?;
+                    let b = self.instantiate(body, args[0]);
+                    e = self.mk_app(b, &args[1..]);
+                    continue;
+                }
+                Expr::Let(_, _, val, body) => {
+                    self.step()?;
+                    let b = self.instantiate(body, val);
+                    e = self.mk_app(b, &args);
+                    continue;
+                }
+                Expr::Constant(n, levels) if self.options.delta => {
+                    // A constant with the wrong number of levels is stuck
+                    let decl = self.env.decl(n);
+                    if let Some(Decl::Def(_, val, params)) =
+                        decl.filter(|d| d.level_params().len() == levels.len())
+                    {
+                        let (val, params) = (*val, params.clone());
+                        self.step()?;
+                        let v = self.instantiate_level_params(val, &params, &levels);
+                        e = self.mk_app(v, &args);
+                        continue;
+                    }
+                }
+                Expr::Proj(_, field, sidx) => {
+                    projs.push((field, args, e));
+                    e = sidx;
+                    continue;
+                }
+                _ => {}
+            }
+            // `e` is in weak head normal form
+            match projs.pop() {
+                None => return Ok(e),
+                Some((field, args, proj)) => match self.constructor_field(field, e) {
+                    Some(f) => {
+                        self.step()?;
+                        e = self.mk_app(f, &args);
+                    }
+                    // The enclosing projections are stuck as well
+                    None => return Ok(projs.first().map_or(proj, |p| p.2)),
+                },
+            }
+        }
+    }
+
+    // Normal form, reducing under binders and in arguments
+    pub fn normalize(&mut self, root: ExprId) -> ReduceResult<ExprId> {
+        // subterm, and its weak head normal form once its children are done
+        let mut stack = vec![(root, None)];
+        let mut results = vec![];
+        while let Some((eidx, whnf)) = stack.pop() {
+            match whnf {
+                Some(w) => {
+                    let count = self.env.expr(w).children().len();
+                    let new_children = results.split_off(results.len() - count);
+                    let result = self.rebuild(w, &new_children);
+                    self.normal_forms.insert(eidx, result);
+                    results.push(result);
+                }
+                None => {
+                    if let Some(r) = self.normal_forms.get(&eidx) {
+                        results.push(*r);
+                        continue;
+                    }
+                    let w = self.whnf(eidx)?;
+                    stack.push((eidx, Some(w)));
+                    let children = self.env.expr(w).children();
+                    stack.extend(children.into_iter().rev().map(|c| (c, None)));
+                }
+            }
+        }
+        Ok(results.pop().expect("No result"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{InfoAnnotation, NameItem};
+    use crate::parser::{parse_lines, ParseOptions};
+    use crate::{term, Builder};
+
+    #[test]
+    fn test_reduce() {
+        /*
+         * def id.{u} {α : Sort u} (a : α) : α := a
+         * def p : Type := @id.{2} Type Prop
+         */
+        let export = "\
+1 #NS 0 id
+2 #NS 0 α
+3 #NS 0 u
+1 #UP 3
+0 #ES 1
+4 #NS 0 a
+1 #EV 0
+2 #EV 1
+3 #EP #BD 4 1 2
+4 #EP #BI 2 0 3
+5 #EL #BD 4 1 1
+6 #EL #BI 2 0 5
+#DEF 1 4 6 3
+5 #NS 0 p
+2 #US 0
+3 #US 2
+7 #ES 2
+8 #ES 0
+9 #EC 1 3
+10 #EA 9 7
+11 #EA 10 8
+#DEF 5 7 11
+";
+        let mut env = parse_lines(export.as_bytes(), &ParseOptions::default(), &mut ()).unwrap();
+        let no_delta = ReduceOptions {
+            delta: false,
+            ..Default::default()
+        };
+        let (value, prop) = (ExprId::from(11), ExprId::from(8));
+        assert_eq!(Reducer::new(&mut env, no_delta).whnf(value), Ok(value));
+        assert_eq!(
+            Reducer::new(&mut env, Default::default()).whnf(value),
+            Ok(prop)
+        );
+        let limited = ReduceOptions {
+            max_steps: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Reducer::new(&mut env, limited).normalize(value),
+            Err(ReduceError::StepLimit(1))
+        );
+    }
+
+    #[test]
+    fn test_level_count() {
+        // def p.{u} := Sort u, and p without levels
+        let mut b = Builder::new();
+        let (ty, val) = (term!(b, (Sort (succ u))), term!(b, (Sort u)));
+        b.def("p", ty, val, &["u"]);
+        let p = b.cnst("p", &[]);
+        let mut env = b.build();
+        assert_eq!(Reducer::new(&mut env, Default::default()).whnf(p), Ok(p));
+    }
+
+    #[test]
+    fn test_instantiate() {
+        // (fun a => #1) (fun a b => #2), with #0 substituted at two depths
+        let mut env = Environment::new();
+        let x = NameId::from(1);
+        env.add_name(x, NameItem::Str("x".to_string()), NameId::from(0))
+            .unwrap();
+        let prop = env.push_expr(Expr::Sort(LevelId::from(0)));
+        let lambda = |env: &mut Environment, body| {
+            env.push_expr(Expr::Lambda(InfoAnnotation::Default, x, prop, body))
+        };
+        let (b0, b1, b2) = (
+            env.push_expr(Expr::BoundVar(0)),
+            env.push_expr(Expr::BoundVar(1)),
+            env.push_expr(Expr::BoundVar(2)),
+        );
+        let f = lambda(&mut env, b1);
+        let inner = lambda(&mut env, b2);
+        let g = lambda(&mut env, inner);
+        let body = env.push_expr(Expr::FunAppl(f, g));
+        // Replacing #0 by itself leaves the term as it is
+        let mut reducer = Reducer::new(&mut env, Default::default());
+        assert_eq!(reducer.instantiate(body, b0), body);
+    }
+
+    #[test]
+    fn test_deep() {
+        /*
+         * axiom c : Prop
+         * axiom f : Prop → Prop
+         * def g := f
+         * def h := f
+         * and terms g (g (... c)) and h (h (... c)) nested 1,000,000 deep
+         */
+        const DEPTH: usize = 1_000_000;
+        let mut env = Environment::new();
+        for (i, name) in ["c", "f", "g", "h", "x"].iter().enumerate() {
+            let item = NameItem::Str(name.to_string());
+            env.add_name(NameId::from(i as u32 + 1), item, NameId::from(0))
+                .unwrap();
+        }
+        let n = |name| env.find_name(name).unwrap();
+        let (c, f, g, h, x) = (n("c"), n("f"), n("g"), n("h"), n("x"));
+        let prop = env.push_expr(Expr::Sort(LevelId::from(0)));
+        let arrow = env.push_expr(Expr::Pi(InfoAnnotation::Default, x, prop, prop));
+        let f_const = env.push_expr(Expr::Constant(f, vec![]));
+        env.add_axiom(c, prop, vec![]).unwrap();
+        env.add_axiom(f, arrow, vec![]).unwrap();
+        env.add_definition(g, arrow, f_const, vec![]).unwrap();
+        env.add_definition(h, arrow, f_const, vec![]).unwrap();
+        let mut nest = |head: NameId, arg: Expr| {
+            let head = env.push_expr(Expr::Constant(head, vec![]));
+            let mut e = env.push_expr(arg);
+            for _ in 0..DEPTH {
+                e = env.push_expr(Expr::FunAppl(head, e));
+            }
+            e
+        };
+        let gs = nest(g, Expr::Constant(c, vec![]));
+        let hs = nest(h, Expr::Constant(c, vec![]));
+        let gx = nest(g, Expr::BoundVar(0));
+        let c_const = env.push_expr(Expr::Constant(c, vec![]));
+        let lambda = env.push_expr(Expr::Lambda(InfoAnnotation::Default, x, prop, gx));
+        let app = env.push_expr(Expr::FunAppl(lambda, c_const));
+
+        let unlimited = || ReduceOptions {
+            max_steps: usize::MAX,
+            ..Default::default()
+        };
+        let mut reducer = Reducer::new(&mut env, unlimited());
+        // Beta reduction substitutes at the bottom of the nested term
+        assert_eq!(reducer.whnf(app), reducer.whnf(gs));
+        assert_eq!(reducer.normalize(app), reducer.normalize(gs));
+        assert_eq!(reducer.normalize(gs), reducer.normalize(hs));
+    }
+}
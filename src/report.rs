@@ -0,0 +1,131 @@
+use std::fmt::Write;
+
+use super::axioms::AxiomDeps;
+use super::checker::DeclResult;
+use super::environment::Environment;
+use super::parser::ParseError;
+
+/*
+ * JSON report of a check run, one record per declaration in export order
+ * followed by a summary. Times are in microseconds. Declarations found in
+ * the verification cache have the status "cached" unless their axioms are
+ * not allowed.
+ *
+ * {"declarations": [
+ *   {"name": "id", "kind": "definition", "status": "ok", "error": null,
+ *    "time_us": 4, "universe_params": ["u"], "axioms": []},
+ *   ...],
+ *  "summary": {"declarations": 1, "ok": 1, "cached": 0, "failed": 0,
+ *   "parse_errors": [], "time_us": 4}}
+ */
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_list<I: IntoIterator<Item = String>>(items: I) -> String {
+    let items = items
+        .into_iter()
+        .map(|s| json_string(&s))
+        .collect::<Vec<_>>();
+    format!("[{}]", items.join(", "))
+}
+
+fn decl_record(env: &Environment, axiom_deps: &mut AxiomDeps, r: &DeclResult) -> String {
+    let decl = env.decl(r.nidx).expect("Declaration not found");
+    let (status, error) = match &r.result {
+        Ok(()) if r.cached => ("cached", "null".to_string()),
+        Ok(()) => ("ok", "null".to_string()),
+        Err(e) => ("failed", json_string(&e.to_string())),
+    };
+    let params = decl.level_params().iter().map(|n| env.name_to_string(*n));
+    let axioms = axiom_deps
+        .axioms(r.nidx)
+        .iter()
+        .map(|n| env.name_to_string(*n));
+    format!(
+        "{{\"name\": {}, \"kind\": {}, \"status\": {}, \"error\": {}, \"time_us\": {}, \
+         \"universe_params\": {}, \"axioms\": {}}}",
+        json_string(&env.name_to_string(r.nidx)),
+        json_string(decl.kind()),
+        json_string(status),
+        error,
+        r.duration.as_micros(),
+        json_list(params),
+        json_list(axioms),
+    )
+}
+
+pub fn json_report(
+    env: &Environment,
+    results: &[DeclResult],
+    parse_errors: &[ParseError],
+) -> String {
+    let mut axiom_deps = AxiomDeps::new(env);
+    let mut out = String::from("{\"declarations\": [");
+    for (i, r) in results.iter().enumerate() {
+        out.push_str(if i == 0 { "\n  " } else { ",\n  " });
+        out.push_str(&decl_record(env, &mut axiom_deps, r));
+    }
+    let failed = results.iter().filter(|r| r.result.is_err()).count();
+    let cached = results
+        .iter()
+        .filter(|r| r.cached && r.result.is_ok())
+        .count();
+    let time: u128 = results.iter().map(|r| r.duration.as_micros()).sum();
+    write!(
+        out,
+        "],\n \"summary\": {{\"declarations\": {}, \"ok\": {}, \"cached\": {}, \
+         \"failed\": {}, \"parse_errors\": {}, \"time_us\": {}}}}}",
+        results.len(),
+        results.len() - failed - cached,
+        cached,
+        failed,
+        json_list(parse_errors.iter().map(|e| e.to_string())),
+        time
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a.b"), "\"a.b\"");
+        assert_eq!(json_string("\"x\"\\\n"), "\"\\\"x\\\"\\\\\\n\"");
+        assert_eq!(json_string("\u{1}α"), "\"\\u0001α\"");
+    }
+
+    #[test]
+    fn test_cached() {
+        let file = std::fs::File::open("examples/id.export").unwrap();
+        let env = crate::parse_lines(file, &Default::default(), &mut ()).unwrap();
+        let nidx = env.find_name("id").unwrap();
+        let results = [DeclResult {
+            nidx,
+            result: Ok(()),
+            duration: Default::default(),
+            cached: true,
+        }];
+        let report = json_report(&env, &results, &[]);
+        assert!(report.contains("\"status\": \"cached\""));
+        assert!(report.contains("\"ok\": 0, \"cached\": 1, \"failed\": 0"));
+    }
+}
@@ -0,0 +1,63 @@
+use super::copy::{Copier, Destination};
+use super::deps::closure_with;
+use super::environment::{Environment, NameId};
+
+/*
+ * The environment of the given declarations and all they depend on, with
+ * only the names, levels and expressions these refer to. Items are
+ * renumbered; declarations keep their relative order, and #QUOT is kept if
+ * one of its constants is used. Notations are dropped.
+ */
+pub fn slice(env: &Environment, targets: &[NameId]) -> Environment {
+    slice_with_axioms(env, targets, &[])
+}
+
+/*
+ * Like slice, but the given definitions become axioms of the same type, so
+ * that what only their values depend on is left out.
+ */
+pub fn slice_with_axioms(env: &Environment, targets: &[NameId], axioms: &[NameId]) -> Environment {
+    let mut dest = Destination::new(Environment::with_dialect(env.dialect()));
+    let mut copier = Copier::new(env, &mut dest, axioms);
+    for nidx in closure_with(env, targets, |n| copier.dependencies(n)) {
+        copier.decl(nidx).expect("Invalid declaration");
+    }
+    dest.env
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::{check_declarations, dependency_closure, parse_lines, write_export, ParseOptions};
+
+    #[test]
+    fn test_slice() {
+        let file = File::open("examples/Nat.gcd_self.export").unwrap();
+        let env = parse_lines(file, &ParseOptions::default(), &mut ()).unwrap();
+        let target = env.find_name("WellFounded.fix").unwrap();
+        let sliced = slice(&env, &[target]);
+        let names = |env: &Environment, nidxs: &[NameId]| {
+            let names = nidxs.iter().map(|n| env.name_to_string(*n));
+            names.collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&sliced, sliced.declarations()),
+            names(&env, &dependency_closure(&env, &[target]))
+        );
+        assert!(sliced.expr_count() < env.expr_count() / 10);
+        let name_idxs = sliced.names().map(|(n, _, _)| n.index());
+        assert!(name_idxs.eq(1..=sliced.name_count()));
+        let expr_idxs = sliced.exprs().map(|(e, _)| e.index());
+        assert!(expr_idxs.eq(0..sliced.expr_count()));
+
+        // The slice loads and checks on its own
+        let mut out = Vec::new();
+        write_export(&sliced, &mut out).unwrap();
+        let loaded = parse_lines(out.as_slice(), &ParseOptions::default(), &mut ()).unwrap();
+        assert!(loaded == sliced);
+        let results = check_declarations(&loaded, loaded.declarations(), &mut ());
+        assert!(results.iter().all(|r| r.result.is_ok()));
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::io::{self, Write};
+
+use super::environment::{Decl, Environment, Expr, InfoAnnotation, Level, NameItem, NotationKind};
+
+fn info_annotation(info: InfoAnnotation) -> &'static str {
+    match info {
+        InfoAnnotation::Default => "#BD",
+        InfoAnnotation::Implicit => "#BI",
+        InfoAnnotation::StrictImplicit => "#BS",
+        InfoAnnotation::InstImplicit => "#BC",
+    }
+}
+
+// Items separated by spaces, each preceded by one
+fn spaced<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|i| format!(" {}", i.to_string()))
+        .collect()
+}
+
+// Names and notation tokens are read up to the next whitespace
+fn single_token(s: &str) -> io::Result<&str> {
+    if s.is_empty() || s.contains(char::is_whitespace) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{:?} cannot be written as a single token", s),
+        ));
+    }
+    Ok(s)
+}
+
+/*
+ * Items in an order in which each comes after the items it refers to. This
+ * is index order unless an item refers to one with a larger index, which
+ * parsing allows when the export defines that one first.
+ */
+fn definition_order<I, R>(items: impl Iterator<Item = I>, refs: impl Fn(I) -> R) -> Vec<I>
+where
+    I: Copy + Eq + Hash,
+    R: IntoIterator<Item = I>,
+{
+    let mut seen = HashSet::new();
+    let mut order = vec![];
+    for item in items {
+        let mut stack = vec![(item, false)];
+        while let Some((i, expanded)) = stack.pop() {
+            if expanded {
+                order.push(i);
+            } else if seen.insert(i) {
+                stack.push((i, true));
+                let refs = refs(i).into_iter().collect::<Vec<_>>();
+                stack.extend(refs.into_iter().rev().map(|r| (r, false)));
+            }
+        }
+    }
+    order
+}
+
+fn write_expr<W: Write>(out: &mut W, expr: &Expr) -> io::Result<()> {
+    match expr {
+        Expr::BoundVar(i) => write!(out, "#EV {}", i),
+        Expr::Sort(u) => write!(out, "#ES {}", u),
+        Expr::Constant(n, us) => write!(out, "#EC {}{}", n, spaced(us)),
+        Expr::FunAppl(e1, e2) => write!(out, "#EA {} {}", e1, e2),
+        Expr::Lambda(info, n, e1, e2) => {
+            write!(out, "#EL {} {} {} {}", info_annotation(*info), n, e1, e2)
+        }
+        Expr::Pi(info, n, e1, e2) => {
+            write!(out, "#EP {} {} {} {}", info_annotation(*info), n, e1, e2)
+        }
+        Expr::Let(n, e1, e2, e3) => write!(out, "#EZ {} {} {} {}", n, e1, e2, e3),
+        Expr::Proj(n, field, e) => write!(out, "#EJ {} {} {}", n, field, e),
+        Expr::NatLit(digits) => write!(out, "#ELN {}", digits),
+        Expr::StrLit(s) => {
+            let hex = s.bytes().map(|b| format!("{:02x}", b));
+            write!(out, "#ELS{}", spaced(hex))
+        }
+    }
+}
+
+/*
+ * Write an environment in the export format, such that parsing the output
+ * gives back an equal environment. Every item keeps its index. Names, levels
+ * and expressions are written first, each in definition order, so that
+ * items only refer to earlier lines; the declarations follow in their
+ * order. Names and tokens containing whitespace cannot be read back and
+ * are an InvalidData error.
+ */
+pub fn write_export<W: Write>(env: &Environment, out: &mut W) -> io::Result<()> {
+    let names = definition_order(env.names().map(|(n, _, _)| n), |n| {
+        Some(env.name(n).1).filter(|p| p.index() != 0)
+    });
+    for idx in names {
+        match env.name(idx) {
+            (NameItem::Str(s), parent) => {
+                writeln!(out, "{} #NS {} {}", idx, parent, single_token(s)?)?
+            }
+            (NameItem::Int(i), parent) => writeln!(out, "{} #NI {} {}", idx, parent, i)?,
+        }
+    }
+    // Level 0 is not part of the export
+    let levels = definition_order(env.levels().map(|(u, _)| u), |u| match *env.level(u) {
+        Level::Zero | Level::Param(_) => vec![],
+        Level::Succ(u1) => vec![u1],
+        Level::Max(u1, u2) | Level::IMax(u1, u2) => vec![u1, u2],
+    });
+    for idx in levels.into_iter().filter(|u| u.index() != 0) {
+        match env.level(idx) {
+            Level::Zero => unreachable!("Level zero at index {}", idx),
+            Level::Succ(u) => writeln!(out, "{} #US {}", idx, u)?,
+            Level::Max(u1, u2) => writeln!(out, "{} #UM {} {}", idx, u1, u2)?,
+            Level::IMax(u1, u2) => writeln!(out, "{} #UIM {} {}", idx, u1, u2)?,
+            Level::Param(n) => writeln!(out, "{} #UP {}", idx, n)?,
+        }
+    }
+    let exprs = definition_order(env.exprs().map(|(e, _)| e), |e| env.expr(e).children());
+    for idx in exprs {
+        write!(out, "{} ", idx)?;
+        write_expr(out, env.expr(idx))?;
+        writeln!(out)?;
+    }
+    if env.has_quot() {
+        writeln!(out, "#QUOT")?;
+    }
+    for nidx in env.declarations() {
+        match env.decl(*nidx).expect("Declaration not found") {
+            Decl::Axiom(eidx, level_names) => {
+                writeln!(out, "#AX {} {}{}", nidx, eidx, spaced(level_names))?
+            }
+            Decl::Def(eidx1, eidx2, level_names) => writeln!(
+                out,
+                "#DEF {} {} {}{}",
+                nidx,
+                eidx1,
+                eidx2,
+                spaced(level_names)
+            )?,
+            Decl::Ind(params, eidx, intros, level_names) => writeln!(
+                out,
+                "#IND {} {} {} {}{}{}",
+                params,
+                nidx,
+                eidx,
+                intros.len(),
+                spaced(intros.iter().map(|(ni, ei)| format!("{} {}", ni, ei))),
+                spaced(level_names)
+            )?,
+        }
+    }
+    for (kind, nidx, prec, token) in env.notations() {
+        let cmd = match kind {
+            NotationKind::Prefix => "#PREFIX",
+            NotationKind::Infix => "#INFIX",
+            NotationKind::Postfix => "#POSTFIX",
+        };
+        writeln!(out, "{} {} {} {}", cmd, nidx, prec, single_token(token)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+    use crate::{parse_lines, term, Builder, Dialect, ParseOptions};
+
+    fn round_trip(env: &Environment) -> Environment {
+        let mut out = Vec::new();
+        write_export(env, &mut out).unwrap();
+        let options = ParseOptions {
+            dialect: env.dialect(),
+        };
+        parse_lines(out.as_slice(), &options, &mut ()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_export() {
+        for file in ["examples/Nat.gcd_self.export", "examples/id.export"] {
+            let options = ParseOptions::default();
+            let env = parse_lines(File::open(file).unwrap(), &options, &mut ()).unwrap();
+            assert!(round_trip(&env) == env, "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_builder() {
+        let mut b = Builder::new();
+        let nat = b.cnst("Nat", &[]);
+        let (ty, succ_ty) = (term!(b, Type), term!(b, ("Nat" -> "Nat")));
+        b.inductive(
+            "Nat",
+            0,
+            ty,
+            &[("Nat.zero", nat), ("Nat.succ", succ_ty)],
+            &[],
+        );
+        let (lit, s) = (b.nat_lit("12"), b.str_lit("a b\n"));
+        let val = term!(b, (let x : "Nat" := {lit}; (fun y : "String" => x)));
+        let ty = term!(b, (pi y : "String", "Nat"));
+        b.def("f", ty, val, &["u"]);
+        let ty = b.cnst("String", &[]);
+        b.def("s", ty, s, &[]);
+        let ty = term!(b, (Sort (max u 1)));
+        b.axiom("g", ty, &["u"]);
+        b.quot();
+        let env = b.build();
+        let copy = round_trip(&env);
+        assert!(copy == env);
+        assert!(copy.has_quot());
+        let f = copy.find_name("f").unwrap();
+        assert_eq!(copy.decl(f), env.decl(f));
+        assert!(copy
+            .exprs()
+            .any(|(_, e)| *e == Expr::StrLit("a b\n".to_string())));
+    }
+
+    #[test]
+    fn test_round_trip_order() {
+        // Items referring to items with larger indices
+        let export = "\
+5 #NS 0 a
+2 #NS 5 b
+3 #US 0
+1 #US 3
+4 #ES 1
+2 #EA 4 4
+#AX 2 2
+";
+        let env = parse_lines(export.as_bytes(), &ParseOptions::default(), &mut ()).unwrap();
+        assert!(round_trip(&env) == env);
+    }
+
+    #[test]
+    fn test_bad_names() {
+        for s in ["a b", "a\nb", ""] {
+            let mut env = Environment::new();
+            env.add_name(1.into(), NameItem::Str(s.to_string()), 0.into())
+                .unwrap();
+            let err = write_export(&env, &mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_notation() {
+        let export = "1 #NS 0 nat\n\
+                      2 #NS 1 add\n\
+                      #INFIX 2 65 +\n\
+                      #PREFIX 2 100 -\n";
+        let options = ParseOptions {
+            dialect: Dialect::Lean3,
+        };
+        let env = parse_lines(export.as_bytes(), &options, &mut ()).unwrap();
+        let mut out = Vec::new();
+        write_export(&env, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), export);
+        assert!(round_trip(&env) == env);
+    }
+}
@@ -0,0 +1,41 @@
+use std::fs::File;
+
+use lean_checker::{
+    check_declarations, parse_lines, AxiomDeps, Decl, Expr, Level, ParseOptions, ReduceOptions,
+    Reducer,
+};
+
+// The library as used by other tools, through its public API only
+#[test]
+fn test_api() {
+    let file = File::open("examples/id.export").unwrap();
+    let mut env = parse_lines(file, &ParseOptions::default(), &mut ()).unwrap();
+    let id = env.find_name("id").unwrap();
+    assert_eq!(env.declarations(), [id]);
+
+    let Some(Decl::Def(_, value, params)) = env.decl(id) else {
+        panic!("id is not a definition");
+    };
+    let value = *value;
+    assert_eq!(
+        params
+            .iter()
+            .map(|n| env.name_to_string(*n))
+            .collect::<Vec<_>>(),
+        ["u"]
+    );
+    let Expr::Lambda(_, _, domain, _) = env.expr(value) else {
+        panic!("The value of id is not a lambda");
+    };
+    let Expr::Sort(u) = env.expr(*domain) else {
+        panic!("The first argument of id is not a type");
+    };
+    assert!(matches!(env.level(*u), Level::Param(_)));
+
+    let results = check_declarations(&env, env.declarations(), &mut ());
+    assert!(results.iter().all(|r| r.result.is_ok()));
+    assert!(AxiomDeps::new(&env).axioms(id).is_empty());
+
+    let mut reducer = Reducer::new(&mut env, ReduceOptions::default());
+    assert_eq!(reducer.normalize(value), Ok(value));
+}
@@ -0,0 +1,42 @@
+use std::process::Command;
+
+// Standard output of the command line tool run on the arguments
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_lean-checker"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// One declaration or several, the lines are the same
+#[test]
+fn test_axioms() {
+    let export = "examples/Nat.gcd_self.export";
+    assert_eq!(
+        run(&["axioms", export, "Nat.gcd_self"]),
+        "Nat.gcd_self: propext\n"
+    );
+    assert_eq!(
+        run(&["axioms", export, "Nat.gcd_self", "Nat"]),
+        "Nat.gcd_self: propext\nNat:\n"
+    );
+}
+
+// A value that is also the name of an option is still a value
+#[test]
+fn test_option_values() {
+    let export = "examples/id.export";
+    assert_eq!(
+        run(&[
+            "check",
+            export,
+            "--allow-axioms",
+            "--target",
+            "--target",
+            "id"
+        ]),
+        ""
+    );
+}